@@ -0,0 +1,45 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use secure_aggregation_tutorial::{sum_vectors, Server};
+use std::num::Wrapping;
+
+// Pairwise interaction costs two X25519 agreements per pair, so at 10000 participants a
+// single initialize takes hours; it is only benchmarked up to 1000.
+fn interaction(c: &mut Criterion) {
+    let mut group = c.benchmark_group("initialize");
+    group.sample_size(10);
+    for num_participants in [100, 1000] {
+        group.bench_with_input(BenchmarkId::from_parameter(num_participants), &num_participants, |b, &n| {
+            b.iter_batched(|| Server::<Wrapping<u32>>::with_seed(0, n, 1), |mut server| server.initialize().unwrap(), BatchSize::LargeInput)
+        });
+    }
+    group.finish();
+}
+
+// Groups of 10 keep the interaction linear in the number of participants.
+fn grouped_interaction(c: &mut Criterion) {
+    let mut group = c.benchmark_group("initialize_grouped");
+    group.sample_size(10);
+    for num_participants in [100, 1000, 10000] {
+        group.bench_with_input(BenchmarkId::from_parameter(num_participants), &num_participants, |b, &n| {
+            b.iter_batched(|| Server::<Wrapping<u32>>::with_seed(0, n, 1), |mut server| server.initialize_grouped(10).unwrap(), BatchSize::LargeInput)
+        });
+    }
+    group.finish();
+}
+
+// Only the sum of the masked values; aggregate() also commits to every submission and
+// checks for overflow, which would dominate the timing.
+fn summation(c: &mut Criterion) {
+    let mut group = c.benchmark_group("aggregate");
+    for num_participants in [100, 1000, 10000] {
+        let mut server = Server::<Wrapping<u32>>::with_seed(0, num_participants, 1);
+        server.initialize_grouped(10).unwrap();
+        group.bench_with_input(BenchmarkId::from_parameter(num_participants), &num_participants, |b, _| {
+            b.iter(|| sum_vectors(server.clients.iter().map(|client| &client.masked_value), 1))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, interaction, grouped_interaction, summation);
+criterion_main!(benches);
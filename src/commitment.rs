@@ -0,0 +1,74 @@
+use crate::MaskableValue;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+pub type Hash = [u8; 32];
+
+// One step from a leaf towards the root: the sibling's hash and which side it sits on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProofStep {
+    pub sibling: Hash,
+    pub sibling_on_left: bool,
+}
+
+// Merkle tree over the masked values the server received. Publishing the root with the
+// aggregate commits the server to exactly these contributions, and an inclusion proof
+// lets a client check that its own masked value is among them. Leaf and node hashes
+// use different prefixes so a leaf can never pass for an inner node.
+#[derive(Serialize, Deserialize)]
+pub struct MerkleTree {
+    // levels[0] are the leaves and the last level is the root. A node without a sibling
+    // is carried up to the next level unchanged.
+    levels: Vec<Vec<Hash>>,
+}
+
+pub fn leaf_hash<T: MaskableValue>(client: usize, masked_value: &[T]) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([0]);
+    hasher.update((client as u32).to_le_bytes());
+    for &x in masked_value {
+        hasher.update(x.to_le_bytes());
+    }
+    hasher.finalize().into()
+}
+
+fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.update([1]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+impl MerkleTree {
+    pub fn new(leaves: Vec<Hash>) -> MerkleTree {
+        let mut levels = vec![leaves];
+        while levels.last().unwrap().len() > 1 {
+            let next = levels.last().unwrap().chunks(2).map(|pair| if pair.len() == 2 { node_hash(&pair[0], &pair[1]) } else { pair[0] }).collect();
+            levels.push(next);
+        }
+        MerkleTree { levels }
+    }
+
+    // The root of an empty tree is all zeros.
+    pub fn root(&self) -> Hash {
+        self.levels.last().unwrap().first().copied().unwrap_or([0; 32])
+    }
+
+    pub fn proof(&self, mut index: usize) -> Vec<ProofStep> {
+        let mut steps = Vec::new();
+        for level in &self.levels[..self.levels.len() - 1] {
+            let sibling = index ^ 1;
+            if sibling < level.len() {
+                steps.push(ProofStep { sibling: level[sibling], sibling_on_left: sibling < index });
+            }
+            index /= 2;
+        }
+        steps
+    }
+}
+
+pub fn verify_inclusion(root: &Hash, leaf: Hash, proof: &[ProofStep]) -> bool {
+    let computed = proof.iter().fold(leaf, |hash, step| if step.sibling_on_left { node_hash(&step.sibling, &hash) } else { node_hash(&hash, &step.sibling) });
+    &computed == root
+}
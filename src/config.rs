@@ -0,0 +1,66 @@
+use crate::experiment::{Config, Mode, Output, Verbosity};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches};
+use serde::Deserialize;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+// Options read from a secagg.toml file. Every key is optional; the file only supplies
+// options that were not given on the command line, and the clap defaults fill the rest.
+#[derive(Debug, Default, Deserialize)]
+pub struct FileConfig {
+    pub mode: Option<Mode>,
+    pub participants: Option<usize>,
+    pub max_participants: Option<usize>,
+    pub seed: Option<u64>,
+    pub dim: Option<usize>,
+    pub dropout_rate: Option<f64>,
+    pub sigma: Option<f64>,
+    pub shuffle_peers: Option<bool>,
+    pub masking_rounds: Option<u32>,
+    pub verify_masks: Option<bool>,
+    pub output: Option<Output>,
+    pub verbosity: Option<Verbosity>,
+    pub csv: Option<PathBuf>,
+}
+
+impl FileConfig {
+    pub fn parse(text: &str) -> Result<FileConfig, String> {
+        toml::from_str(text).map_err(|err| format!("invalid config file: {}", err))
+    }
+}
+
+// Parses the process arguments, letting clap handle --help and usage errors itself.
+pub fn load() -> Result<Config, String> {
+    from_matches(&Config::command().get_matches())
+}
+
+// A missing file at the default path is fine; a missing file passed with --config is not.
+pub fn from_matches(matches: &ArgMatches) -> Result<Config, String> {
+    let mut config = Config::from_arg_matches(matches).map_err(|err| err.to_string())?;
+    let explicit = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+    let file = match fs::read_to_string(&config.config) {
+        Ok(text) => FileConfig::parse(&text)?,
+        Err(err) if err.kind() == ErrorKind::NotFound && !explicit("config") => FileConfig::default(),
+        Err(err) => return Err(format!("cannot read {}: {}", config.config.display(), err)),
+    };
+    macro_rules! fill_from_file {
+        ($($field:ident),*) => {$(
+            if let Some(value) = file.$field {
+                if !explicit(stringify!($field)) {
+                    config.$field = value;
+                }
+            }
+        )*};
+    }
+    fill_from_file!(mode, participants, max_participants, dim, dropout_rate, sigma, shuffle_peers, masking_rounds, verify_masks, output, verbosity);
+    if config.seed.is_none() {
+        config.seed = file.seed;
+    }
+    if config.csv.is_none() {
+        config.csv = file.csv;
+    }
+    config.validate().map_err(|err| err.to_string())?;
+    Ok(config)
+}
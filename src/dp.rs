@@ -0,0 +1,43 @@
+use crate::MaskableValue;
+use rand::Rng;
+use std::f64::consts::PI;
+
+// Differential-privacy noise a client adds to its value before masking. The noise scale is
+// in the value's own fixed-point units: the real-valued noise is rounded to the nearest unit
+// and added with wrapping arithmetic, so negative noise wraps around just like a mask does.
+//
+// Larger noise hides each client better but blurs the result: the noise of n clients adds
+// up to a standard deviation of sigma * sqrt(n) on the aggregate.
+#[derive(Clone, Copy, Debug)]
+pub enum Noise {
+    Gaussian { sigma: f64 },
+    Laplace { scale: f64 },
+}
+
+pub fn add_noise<T: MaskableValue>(value: &mut T, noise: Noise, rng: &mut impl Rng) {
+    match noise {
+        Noise::Gaussian { sigma } => add_gaussian_noise(value, sigma, rng),
+        Noise::Laplace { scale } => add_laplace_noise(value, scale, rng),
+    }
+}
+
+pub fn add_gaussian_noise<T: MaskableValue>(value: &mut T, sigma: f64, rng: &mut impl Rng) {
+    add_fixed_point(value, sample_gaussian(sigma, rng));
+}
+
+// Box-Muller transform; 1 - u keeps the logarithm's argument in (0, 1].
+pub fn sample_gaussian(sigma: f64, rng: &mut impl Rng) -> f64 {
+    let u1: f64 = 1.0 - rng.gen::<f64>();
+    let u2: f64 = rng.gen();
+    sigma * (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+}
+
+pub fn add_laplace_noise<T: MaskableValue>(value: &mut T, scale: f64, rng: &mut impl Rng) {
+    // An exponential sample with a random sign is Laplace distributed.
+    let magnitude = -scale * (1.0 - rng.gen::<f64>()).ln();
+    add_fixed_point(value, if rng.gen() { magnitude } else { -magnitude });
+}
+
+fn add_fixed_point<T: MaskableValue>(value: &mut T, noise: f64) {
+    *value = value.wrapping_add(T::from_i64(noise.round() as i64));
+}
@@ -0,0 +1,69 @@
+use crate::Phase;
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum AggregationError {
+    // A client submitted a vector whose length differs from the server's dim.
+    DimensionMismatch { expected: usize, got: usize },
+    // A dropped client whose masks cannot be recovered from the survivors.
+    MissingClient(u32),
+    // Fewer survivors than the Shamir threshold are left to reconstruct the masks.
+    NotEnoughShares { threshold: usize, survivors: usize },
+    // A survivor saw too few peers sign the survivor set it was told about and aborted.
+    InconsistentSurvivors(u32),
+    // The largest honest aggregate does not fit in the value type's modulus.
+    Overflow,
+    // No client contributed, so there is nothing to average over.
+    NoContributors,
+    // A client was asked to mask with a peer it has not completed a handshake with.
+    NoHandshake,
+    // Masked values were summed before every client in the group had masked.
+    NotInteracted,
+    // A participant count of zero or above the configured maximum.
+    InvalidParticipants { requested: usize, max: usize },
+    // Aggregation was asked for before any client masked its value.
+    NotInitialized,
+    // A dropout rate outside 0.0..=1.0.
+    InvalidDropoutRate,
+    // Zero masking rounds, which would leave every value unmasked.
+    InvalidMaskingRounds,
+    // A client reused the public key of an earlier client, so the two would share masks.
+    DuplicateClient(u32),
+    // The round was in this phase, which does not allow the requested step.
+    WrongPhase(Phase),
+    // Only an ungrouped round whose keys have not been shared yet can take a late client.
+    CannotJoin,
+}
+
+impl fmt::Display for AggregationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AggregationError::DimensionMismatch { expected, got } => {
+                write!(f, "expected a vector of dimension {}, got {}", expected, got)
+            }
+            AggregationError::MissingClient(id) => write!(f, "client {} dropped and its masks cannot be recovered", id),
+            AggregationError::NotEnoughShares { threshold, survivors } => {
+                write!(f, "{} survivors cannot meet the Shamir threshold of {}", survivors, threshold)
+            }
+            AggregationError::InconsistentSurvivors(id) => {
+                write!(f, "client {} aborted: peers disagree on the set of surviving clients", id)
+            }
+            AggregationError::Overflow => write!(f, "the aggregate can overflow the value type's modulus"),
+            AggregationError::NoContributors => write!(f, "no client contributed to the aggregate"),
+            AggregationError::NoHandshake => write!(f, "refusing to mask with a peer that has not completed a handshake"),
+            AggregationError::NotInteracted => write!(f, "not every client in the group has masked its value yet"),
+            AggregationError::InvalidParticipants { requested, max } => {
+                write!(f, "{} participants is outside the supported range of 1 to {}", requested, max)
+            }
+            AggregationError::NotInitialized => write!(f, "cannot aggregate before the clients are initialized"),
+            AggregationError::InvalidDropoutRate => write!(f, "the dropout rate must be between 0 and 1"),
+            AggregationError::InvalidMaskingRounds => write!(f, "need at least one masking round"),
+            AggregationError::DuplicateClient(id) => write!(f, "client {} shares its public key with an earlier client", id),
+            AggregationError::WrongPhase(phase) => write!(f, "not allowed while the round is in the {:?} phase", phase),
+            AggregationError::CannotJoin => write!(f, "a client can only join an ungrouped round before share_keys"),
+        }
+    }
+}
+
+impl Error for AggregationError {}
@@ -0,0 +1,271 @@
+use crate::dp::Noise;
+use crate::error::AggregationError;
+use crate::secret_sharing::MAX_SHARES;
+use crate::{check_participants, mask_peer_to_peer, ring_aggregate, sum_vectors, Server, MAX_PARTICIPANTS};
+use clap::{Parser, ValueEnum};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::fs::OpenOptions;
+use std::io;
+use std::num::Wrapping;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing::info;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Mode {
+    /// Clients send their values unmasked
+    Naive,
+    /// Pairwise masks from X25519 key agreement
+    Pairwise,
+    /// Self-mask plus pairwise masks, with dropouts recovered through Shamir shares
+    DoubleMask,
+    /// Gaussian noise added before pairwise masking
+    Dp,
+    /// Clients mask among themselves and sum around a ring, with no server
+    Ring,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Output {
+    /// Human-readable log lines
+    Text,
+    /// A single JSON object on stdout
+    Json,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Verbosity {
+    /// Only the final result, warnings and errors
+    Quiet,
+    /// The run's settings and both aggregates
+    Normal,
+    /// Also every interaction between clients
+    Verbose,
+}
+
+impl Verbosity {
+    // Directives for tracing_subscriber's EnvFilter. The final result is logged with the
+    // result target so that quiet can keep it and drop everything else.
+    pub fn filter(self) -> &'static str {
+        match self {
+            Verbosity::Quiet => "warn,result=info",
+            Verbosity::Normal => "info",
+            Verbosity::Verbose => "info,secure_aggregation_tutorial=debug",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Parser)]
+#[command(about = "Simulate secure aggregation between in-process clients and a server")]
+pub struct Config {
+    /// Aggregation scheme to simulate
+    #[arg(long, value_enum, default_value_t = Mode::Pairwise)]
+    pub mode: Mode,
+    /// Number of clients (at most 255 in double-mask mode)
+    #[arg(long, default_value_t = 100)]
+    pub participants: usize,
+    /// Largest number of clients a run accepts, at most the default
+    #[arg(long, default_value_t = MAX_PARTICIPANTS)]
+    pub max_participants: usize,
+    /// Seed for a reproducible run
+    #[arg(long)]
+    pub seed: Option<u64>,
+    /// Length of each client's value vector
+    #[arg(long, default_value_t = 1)]
+    pub dim: usize,
+    /// Fraction of clients that drop out in double-mask mode
+    #[arg(long, default_value_t = 0.1)]
+    pub dropout_rate: f64,
+    /// Standard deviation of the noise in dp mode
+    #[arg(long, default_value_t = 2.0)]
+    pub sigma: f64,
+    /// Mask peer pairs in a random order
+    #[arg(long)]
+    pub shuffle_peers: bool,
+    /// Check after aggregation that every pairwise mask cancelled
+    #[arg(long)]
+    pub verify_masks: bool,
+    /// Times each pair masks, with a fresh mask every time
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub masking_rounds: u32,
+    /// Format of the results
+    #[arg(long, value_enum, default_value_t = Output::Text)]
+    pub output: Output,
+    /// How much to log; RUST_LOG overrides this
+    #[arg(long, value_enum, default_value_t = Verbosity::Normal)]
+    pub verbosity: Verbosity,
+    /// CSV file to append a row of round metadata to
+    #[arg(long)]
+    pub csv: Option<PathBuf>,
+    /// TOML file supplying any option not given on the command line
+    #[arg(long, default_value = "secagg.toml")]
+    pub config: PathBuf,
+}
+
+impl Config {
+    // Catches values clap cannot range-check on its own, including those read from a file.
+    pub fn validate(&self) -> Result<(), AggregationError> {
+        // Clients are always created under the library-wide cap, so a higher limit cannot be honoured.
+        check_participants(self.max_participants, MAX_PARTICIPANTS)?;
+        let max = match self.mode {
+            // Each client hands every peer a Shamir share, and shares have one-byte x-coordinates.
+            Mode::DoubleMask => self.max_participants.min(MAX_SHARES),
+            _ => self.max_participants,
+        };
+        check_participants(self.participants, max)?;
+        if !(0.0..=1.0).contains(&self.dropout_rate) {
+            return Err(AggregationError::InvalidDropoutRate);
+        }
+        if self.masking_rounds == 0 {
+            return Err(AggregationError::InvalidMaskingRounds);
+        }
+        Ok(())
+    }
+}
+
+// The server's result next to the sum of the very values clients submitted, unmasked:
+// true values in naive and pairwise mode, the survivors' values in double-mask mode and
+// the noisy values in dp mode.
+pub struct Outcome {
+    pub naive_aggregate: Vec<Wrapping<u32>>,
+    pub secure_aggregate: Vec<Wrapping<u32>>,
+    pub dropouts: usize,
+}
+
+impl Outcome {
+    pub fn matches(&self) -> bool {
+        self.naive_aggregate == self.secure_aggregate
+    }
+
+    // A mismatch means the protocol is broken, so the binary fails and CI notices.
+    pub fn exit_code(&self) -> i32 {
+        if self.matches() {
+            0
+        } else {
+            1
+        }
+    }
+}
+
+pub fn format_vector<T: Display>(vector: &[T]) -> String {
+    vector.iter().map(|x| x.to_string()).collect::<Vec<String>>().join(", ")
+}
+
+// The human-readable result, as --output text prints it.
+pub fn log_outcome(outcome: &Outcome) {
+    info!(target: "result", "Server Aggregate result: {}", format_vector(&outcome.secure_aggregate));
+    info!("Naive Aggregate result: {}", format_vector(&outcome.naive_aggregate));
+}
+
+// Machine-readable summary of a run, printed by --output json.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Report {
+    pub mode: Mode,
+    pub participants: usize,
+    pub naive_aggregate: Vec<u32>,
+    pub secure_aggregate: Vec<u32>,
+    #[serde(rename = "match")]
+    pub matches: bool,
+}
+
+impl Report {
+    pub fn new(config: &Config, outcome: &Outcome) -> Report {
+        Report {
+            mode: config.mode,
+            participants: config.participants,
+            naive_aggregate: outcome.naive_aggregate.iter().map(|x| x.0).collect(),
+            secure_aggregate: outcome.secure_aggregate.iter().map(|x| x.0).collect(),
+            matches: outcome.matches(),
+        }
+    }
+}
+
+// One row of the --csv file. The aggregate is a space-separated list so each round
+// stays a single row whatever the dimension.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RoundRecord {
+    pub round: u64,
+    pub mode: Mode,
+    pub participants: usize,
+    pub dropouts: usize,
+    pub aggregate: String,
+    pub wall_clock_ms: f64,
+    #[serde(rename = "match")]
+    pub matches: bool,
+}
+
+// Appends the round to the CSV file at path, writing the header first if the file is
+// new, and returns the round's id: one more than the rows already there. The file is
+// locked while it is counted and written, so concurrent runs get distinct ids and
+// never interleave their rows.
+pub fn append_csv(path: &Path, config: &Config, outcome: &Outcome, elapsed: Duration) -> io::Result<u64> {
+    let file = OpenOptions::new().read(true).append(true).create(true).open(path)?;
+    file.lock()?;
+    let existing = csv::Reader::from_reader(&file).records().count() as u64;
+    let record = RoundRecord {
+        round: existing + 1,
+        mode: config.mode,
+        participants: config.participants,
+        dropouts: outcome.dropouts,
+        aggregate: outcome.secure_aggregate.iter().map(|x| x.to_string()).collect::<Vec<String>>().join(" "),
+        wall_clock_ms: elapsed.as_secs_f64() * 1000.0,
+        matches: outcome.matches(),
+    };
+    let mut writer = csv::WriterBuilder::new().has_headers(file.metadata()?.len() == 0).from_writer(&file);
+    writer.serialize(&record)?;
+    writer.flush()?;
+    Ok(record.round)
+}
+
+pub fn run(config: &Config) -> Result<Outcome, AggregationError> {
+    config.validate()?;
+    let dim = config.dim;
+    let mut server: Server<Wrapping<u32>> = match config.seed {
+        Some(seed) => Server::with_seed(seed, config.participants, dim),
+        None => Server::new_random(config.participants, dim),
+    };
+    if config.shuffle_peers {
+        server.shuffle_peers();
+    }
+    server.set_masking_rounds(config.masking_rounds);
+    let outcome = match config.mode {
+        Mode::Naive => {
+            let naive_aggregate = sum_vectors(server.clients.iter().map(|client| &client.value), dim);
+            Outcome { secure_aggregate: naive_aggregate.clone(), naive_aggregate, dropouts: 0 }
+        }
+        Mode::Pairwise => {
+            let naive_aggregate = sum_vectors(server.clients.iter().map(|client| &client.value), dim);
+            server.initialize()?;
+            Outcome { naive_aggregate, secure_aggregate: server.aggregate()?, dropouts: 0 }
+        }
+        Mode::DoubleMask => {
+            server.share_keys(config.participants / 2 + 1);
+            server.initialize_double_masked()?;
+            server.simulate_dropout(config.dropout_rate);
+            let survivors = server.survivors();
+            let naive_aggregate = sum_vectors(survivors.iter().map(|&v| &server.clients[v].value), dim);
+            let dropouts = server.clients.len() - survivors.len();
+            Outcome { naive_aggregate, secure_aggregate: server.aggregate_with_recovery()?, dropouts }
+        }
+        Mode::Dp => {
+            server.add_dp_noise(Noise::Gaussian { sigma: config.sigma });
+            let naive_aggregate = sum_vectors(server.clients.iter().map(|client| &client.masked_value), dim);
+            server.initialize()?;
+            Outcome { naive_aggregate, secure_aggregate: server.aggregate()?, dropouts: 0 }
+        }
+        Mode::Ring => {
+            // The server only creates the clients; SeededPairwise draws nothing from the rng.
+            let naive_aggregate = sum_vectors(server.clients.iter().map(|client| &client.value), dim);
+            mask_peer_to_peer(&mut server.clients, &mut rand::thread_rng());
+            Outcome { naive_aggregate, secure_aggregate: ring_aggregate(&server.clients), dropouts: 0 }
+        }
+    };
+    if config.verify_masks {
+        server.verify_masks();
+    }
+    Ok(outcome)
+}
@@ -0,0 +1,99 @@
+use crate::MaskableValue;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::ops::{Add, Sub};
+
+// Integers modulo a prime P, the explicit counterpart of the mod 2^32 arithmetic the
+// Wrapping types give for free. Shamir reconstruction needs a field like this, and masks
+// drawn uniformly from [0, P) cancel exactly as they do in the wrapping ring. P must be
+// below 2^63 so that a + b never overflows the u64 representation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrimeField<const P: u64>(pub u64);
+
+// 2^31 - 1 and 2^61 - 1
+pub type Mersenne31 = PrimeField<2_147_483_647>;
+pub type Mersenne61 = PrimeField<2_305_843_009_213_693_951>;
+
+impl<const P: u64> Add for PrimeField<P> {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        PrimeField((self.0 + other.0) % P)
+    }
+}
+
+impl<const P: u64> Sub for PrimeField<P> {
+    type Output = Self;
+
+    // Adding P first keeps the difference non-negative when other > self.
+    fn sub(self, other: Self) -> Self {
+        PrimeField((self.0 + P - other.0) % P)
+    }
+}
+
+impl<const P: u64> PrimeField<P> {
+    // Rejection sampling: draw as many bits as P - 1 needs and retry anything >= P. Taking
+    // a random u64 mod P instead would favour small residues, and a biased mask leaks.
+    pub fn sample_uniform(rng: &mut impl Rng) -> Self {
+        let bits = u64::MAX >> (P - 1).leading_zeros();
+        loop {
+            let x = rng.gen::<u64>() & bits;
+            if x < P {
+                return PrimeField(x);
+            }
+        }
+    }
+}
+
+impl<const P: u64> MaskableValue for PrimeField<P> {
+    fn zero() -> Self {
+        PrimeField(0)
+    }
+
+    fn from_u32(x: u32) -> Self {
+        PrimeField(x as u64 % P)
+    }
+
+    fn from_i64(x: i64) -> Self {
+        PrimeField((x as i128).rem_euclid(P as i128) as u64)
+    }
+
+    fn to_f64(self) -> f64 {
+        self.0 as f64
+    }
+
+    fn to_i128(self) -> i128 {
+        self.0 as i128
+    }
+
+    fn modulus() -> u128 {
+        P as u128
+    }
+
+    fn random(rng: &mut impl Rng) -> Self {
+        PrimeField::sample_uniform(rng)
+    }
+
+    fn wrapping_add(self, other: Self) -> Self {
+        self + other
+    }
+
+    fn wrapping_sub(self, other: Self) -> Self {
+        self - other
+    }
+
+    fn wrapping_mul(self, other: Self) -> Self {
+        PrimeField((self.0 as u128 * other.0 as u128 % P as u128) as u64)
+    }
+
+    fn to_le_bytes(self) -> Vec<u8> {
+        self.0.to_le_bytes().to_vec()
+    }
+}
+
+impl<const P: u64> fmt::Display for PrimeField<P> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
@@ -0,0 +1,17 @@
+mod simulation;
+mod value;
+
+pub mod commitment;
+pub mod config;
+pub mod dp;
+pub mod error;
+pub mod experiment;
+pub mod field;
+pub mod mask;
+pub mod quant;
+pub mod secret_sharing;
+
+pub use error::AggregationError;
+pub use mask::prg_mask;
+pub use simulation::{check_participants, mask_peer_to_peer, pairs, pairwise_mask, pairwise_mask_round, ring_aggregate, sum_vectors, verify_result, BoundedPairwise, Client, MaskingStrategy, Phase, RandomPairwise, SeededPairwise, Server, ValueDist, MAX_CLIENT_VALUE, MAX_PARTICIPANTS, MIN_CLIENT_VALUE};
+pub use value::MaskableValue;
@@ -1,20 +1,39 @@
-use rand::{distributions::Uniform, Rng};
-use std::num::Wrapping;
+use secure_aggregation_tutorial::config;
+use secure_aggregation_tutorial::experiment::{self, Output, Report};
+use std::error::Error;
+use std::io;
+use std::process;
+use std::time::Instant;
+use tracing::{error, info};
+use tracing_subscriber::EnvFilter;
 
-fn main() {
-    let num_participants = 1000;
-    let range = Uniform::from(5..12);
-    let client_vals: Vec<Wrapping<u32>> = rand::thread_rng().sample_iter(&range).take(num_participants).map(|x| Wrapping(x)).collect();
-    let mut masked_vals: Vec<Wrapping<u32>> = client_vals.clone();
-    for i in 0..num_participants {
-        for j in i+1..num_participants {
-            let masking_val: Wrapping<u32> = Wrapping(rand::thread_rng().gen());
-            masked_vals[i] = masked_vals[i] + masking_val;
-            masked_vals[j] = masked_vals[j] - masking_val;
-        }
+fn main() -> Result<(), Box<dyn Error>> {
+    let config = config::load().unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(2);
+    });
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(config.verbosity.filter())))
+        .with_target(false)
+        .with_writer(io::stderr)
+        .init();
+    info!(mode = ?config.mode, participants = config.participants, seed = ?config.seed, dim = config.dim, "Secure aggregation simulation");
+    let started = Instant::now();
+    let outcome = experiment::run(&config)?;
+    if let Some(path) = &config.csv {
+        let round = experiment::append_csv(path, &config, &outcome, started.elapsed())?;
+        info!(round, path = %path.display(), "Appended round to CSV");
     }
-    let naive_aggregate: Wrapping<u32> = client_vals.iter().sum();
-    let server_aggregate: Wrapping<u32> = masked_vals.iter().sum();
-    println!("Server Aggregate result: {:.2}", server_aggregate);
-    println!("Naive Aggregate result: {:.2}", naive_aggregate);
-}
\ No newline at end of file
+    match config.output {
+        Output::Text => experiment::log_outcome(&outcome),
+        Output::Json => println!("{}", serde_json::to_string(&Report::new(&config, &outcome))?),
+    }
+    if !outcome.matches() {
+        error!(
+            "Server Aggregate {} does not match Naive Aggregate {}",
+            experiment::format_vector(&outcome.secure_aggregate),
+            experiment::format_vector(&outcome.naive_aggregate)
+        );
+    }
+    process::exit(outcome.exit_code());
+}
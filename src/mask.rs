@@ -0,0 +1,10 @@
+use crate::MaskableValue;
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+
+// Expands a 32-byte pairwise seed into a mask vector. Both members of a pair get the
+// same sequence from the same seed: one adds it and the other subtracts it.
+pub fn prg_mask<T: MaskableValue>(seed: [u8; 32], dim: usize) -> Vec<T> {
+    let mut prg = ChaCha20Rng::from_seed(seed);
+    (0..dim).map(|_| T::random(&mut prg)).collect()
+}
@@ -0,0 +1,29 @@
+use std::num::Wrapping;
+
+// Fixed-point encoding of real-valued model updates into the Wrapping<u32> domain. A value
+// x becomes round(x * scale), clamped to a signed bits-bit range and stored in two's
+// complement, so negative updates wrap around like masks do. The rounding error is at most
+// 0.5 / scale per value.
+//
+// Sums of n clients must still fit in an i32 to be decoded: keep bits + log2(n) <= 32.
+pub fn quantize(x: f64, scale: f64, bits: u32) -> Wrapping<u32> {
+    assert!((1..=32).contains(&bits), "bits must be between 1 and 32");
+    let max = ((1i64 << (bits - 1)) - 1) as f64;
+    let q = (x * scale).round().clamp(-max - 1.0, max);
+    Wrapping(q as i64 as u32)
+}
+
+// Decodes an aggregate of n_clients quantized values back into their mean.
+pub fn dequantize(q: Wrapping<u32>, scale: f64, n_clients: u32) -> f64 {
+    q.0 as i32 as f64 / scale / n_clients as f64
+}
+
+// The same encoding into Wrapping<i64>, for signed updates that need more room than 32 bits
+// give. Sums decode correctly while they stay within the i64 range.
+pub fn quantize_i64(x: f64, scale: f64) -> Wrapping<i64> {
+    Wrapping((x * scale).round() as i64)
+}
+
+pub fn dequantize_i64(q: Wrapping<i64>, scale: f64, n_clients: u32) -> f64 {
+    q.0 as f64 / scale / n_clients as f64
+}
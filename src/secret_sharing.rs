@@ -0,0 +1,70 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+// Shamir secret sharing of 32-byte secrets, byte by byte over GF(256). Share x-coordinates
+// are 1..=n, so at most 255 shares can be handed out.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Share {
+    pub x: u8,
+    pub y: [u8; 32],
+}
+
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        let carry = a & 0x80;
+        a <<= 1;
+        if carry != 0 {
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+// a^254 is the multiplicative inverse of a in GF(256).
+fn gf_inv(a: u8) -> u8 {
+    let mut result = 1;
+    for _ in 0..254 {
+        result = gf_mul(result, a);
+    }
+    result
+}
+
+pub const MAX_SHARES: usize = 255;
+
+// Splits the secret into n shares, any threshold of which reconstruct it.
+pub fn split(secret: [u8; 32], n: usize, threshold: usize, rng: &mut impl Rng) -> Vec<Share> {
+    assert!(threshold >= 1 && threshold <= n && n <= MAX_SHARES, "need 1 <= threshold <= n <= 255");
+    let mut shares: Vec<Share> = (1..=n).map(|x| Share { x: x as u8, y: [0; 32] }).collect();
+    for (k, &secret_byte) in secret.iter().enumerate() {
+        let mut coefficients = vec![secret_byte];
+        coefficients.extend((1..threshold).map(|_| rng.gen::<u8>()));
+        for share in shares.iter_mut() {
+            // Horner evaluation of the byte's polynomial at share.x.
+            share.y[k] = coefficients.iter().rev().fold(0, |acc, &c| gf_mul(acc, share.x) ^ c);
+        }
+    }
+    shares
+}
+
+// Lagrange interpolation at x = 0. Needs at least threshold shares with distinct x.
+pub fn reconstruct(shares: &[Share]) -> [u8; 32] {
+    let mut secret = [0; 32];
+    for (i, share) in shares.iter().enumerate() {
+        let mut basis = 1;
+        for (j, other) in shares.iter().enumerate() {
+            if i != j {
+                // In GF(256) subtraction is xor, so (0 - x_j) / (x_i - x_j) = x_j / (x_i ^ x_j).
+                basis = gf_mul(basis, gf_mul(other.x, gf_inv(share.x ^ other.x)));
+            }
+        }
+        for (secret_byte, &y) in secret.iter_mut().zip(share.y.iter()) {
+            *secret_byte ^= gf_mul(basis, y);
+        }
+    }
+    secret
+}
@@ -0,0 +1,1009 @@
+use crate::commitment::{self, Hash, MerkleTree, ProofStep};
+use crate::dp::{self, Noise};
+use crate::error::AggregationError;
+use crate::mask;
+use crate::quant;
+use crate::secret_sharing::{self, Share};
+use crate::MaskableValue;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use rand::{distributions::Uniform, rngs::StdRng, seq::index, seq::SliceRandom, CryptoRng, Rng, RngCore, SeedableRng};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::num::Wrapping;
+use std::path::Path;
+use tracing::{debug, info, warn};
+use x25519_dalek::{PublicKey, StaticSecret};
+
+// Honest clients draw their values from this range, as in the sleep-hours survey.
+pub const MIN_CLIENT_VALUE: u32 = 5;
+pub const MAX_CLIENT_VALUE: u32 = 11;
+
+// Where a round is: aggregating needs masked values, clients join only a masked round,
+// and a round that was aggregated is not masked again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Phase {
+    Idle,
+    Initialized,
+    Aggregated,
+}
+
+// Default cap on the number of clients, well above anything the O(n^2) masking finishes
+// but low enough that a typo cannot allocate gigabytes of keys.
+pub const MAX_PARTICIPANTS: usize = 1_000_000;
+
+// Distribution the simulated clients draw each coordinate of their value from.
+#[derive(Clone, Copy, Debug)]
+pub enum ValueDist {
+    // Every integer in low..=high equally likely.
+    Uniform { low: u32, high: u32 },
+    Constant(u32),
+    // Rank k in 1..=n with probability proportional to 1 / k^s.
+    Zipf { n: u32, s: f64 },
+    // Rounded to the nearest integer and clamped into 0..=mean + 6 std_dev, so that honest
+    // clients stay bounded; a draw beyond six deviations is about one in a billion.
+    Normal { mean: f64, std_dev: f64 },
+}
+
+impl Default for ValueDist {
+    fn default() -> ValueDist {
+        ValueDist::Uniform { low: MIN_CLIENT_VALUE, high: MAX_CLIENT_VALUE }
+    }
+}
+
+impl ValueDist {
+    pub fn sample(&self, rng: &mut impl Rng) -> u32 {
+        match *self {
+            ValueDist::Uniform { low, high } => rng.sample(Uniform::new_inclusive(low, high)),
+            ValueDist::Constant(value) => value,
+            ValueDist::Zipf { n, s } => {
+                // Inverse CDF by a linear scan, fine for the small n of a simulation.
+                let weights: Vec<f64> = (1..=n).map(|k| (k as f64).powf(-s)).collect();
+                let mut target = rng.gen::<f64>() * weights.iter().sum::<f64>();
+                for (k, weight) in (1..=n).zip(&weights) {
+                    if target < *weight {
+                        return k;
+                    }
+                    target -= weight;
+                }
+                n
+            }
+            ValueDist::Normal { mean, std_dev } => (mean + dp::sample_gaussian(std_dev, rng)).round().clamp(0.0, self.max() as f64) as u32,
+        }
+    }
+
+    // Largest value the distribution can produce, which bounds an honest client.
+    pub fn max(&self) -> u32 {
+        match *self {
+            ValueDist::Uniform { high, .. } => high,
+            ValueDist::Constant(value) => value,
+            ValueDist::Zipf { n, .. } => n,
+            ValueDist::Normal { mean, std_dev } => (mean + 6.0 * std_dev).round().clamp(0.0, u32::MAX as f64) as u32,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Client<T: MaskableValue> {
+    pub value: Vec<T>,
+    pub masked_value: Vec<T>,
+    // Sum of every mask this client added minus every mask it subtracted; noise and
+    // clipping are not masks and are not counted.
+    pub net_mask: Vec<T>,
+    // How many samples the value stands for, e.g. the local dataset size in federated averaging.
+    pub weight: u32,
+    secret_key: StaticSecret,
+    pub public_key: PublicKey,
+    signing_key: SigningKey,
+    pub verifying_key: VerifyingKey,
+    // Seed of the mask this client adds on top of its pairwise masks in double-masking mode.
+    self_mask_seed: [u8; 32],
+    // key_shares[i] and self_mask_shares[i] are this client's shares of client i's secret
+    // key and self-mask seed.
+    key_shares: Vec<Share>,
+    self_mask_shares: Vec<Share>,
+    // Public keys of the peers this client completed a handshake with. It only masks
+    // with these, so a key swapped in after the handshake cannot inject a mask.
+    pinned_peers: HashSet<PublicKey>,
+    pub dropped: bool,
+    // Set once this client has masked, so repeating initialize cannot stack extra masks.
+    pub interacted: bool,
+}
+
+impl<T: MaskableValue> Client<T> {
+    pub fn new(value: Vec<T>, rng: &mut (impl Rng + CryptoRng)) -> Client<T> {
+        let secret_key = StaticSecret::random_from_rng(&mut *rng);
+        let public_key = PublicKey::from(&secret_key);
+        let signing_key = SigningKey::generate(rng);
+        let verifying_key = signing_key.verifying_key();
+        let self_mask_seed = rng.gen();
+        Client {
+            masked_value: value.clone(),
+            net_mask: vec![T::zero(); value.len()],
+            value,
+            weight: 1,
+            secret_key,
+            public_key,
+            signing_key,
+            verifying_key,
+            self_mask_seed,
+            key_shares: Vec::new(),
+            self_mask_shares: Vec::new(),
+            pinned_peers: HashSet::new(),
+            dropped: false,
+            interacted: false,
+        }
+    }
+
+    pub fn initialize_multiple(num_participants: usize, dim: usize, rng: &mut (impl Rng + CryptoRng)) -> Result<Vec<Client<T>>, AggregationError> {
+        Client::initialize_multiple_with(ValueDist::default(), num_participants, dim, rng)
+    }
+
+    pub fn initialize_multiple_with(dist: ValueDist, num_participants: usize, dim: usize, rng: &mut (impl Rng + CryptoRng)) -> Result<Vec<Client<T>>, AggregationError> {
+        check_participants(num_participants, MAX_PARTICIPANTS)?;
+        Ok((0..num_participants)
+            .map(|_| {
+                let value = (0..dim).map(|_| T::from_u32(dist.sample(rng))).collect();
+                Client::new(value, rng)
+            })
+            .collect())
+    }
+
+    // Scales the value down so its L2 norm is at most max; for a scalar this just caps
+    // its magnitude. Must run before masking, as it changes the true value.
+    pub fn clip(&mut self, max: u32) -> Result<(), AggregationError> {
+        if self.interacted {
+            return Err(AggregationError::WrongPhase(Phase::Initialized));
+        }
+        let norm = self.value.iter().map(|x| x.to_f64().powi(2)).sum::<f64>().sqrt();
+        if norm > max as f64 {
+            let factor = max as f64 / norm;
+            self.value = self.value.iter().map(|x| T::from_i64((x.to_f64() * factor) as i64)).collect();
+            self.masked_value = self.value.clone();
+        }
+        Ok(())
+    }
+
+    // Multiplies the value by weight so the aggregate is a weighted sum. Like clip, this
+    // changes the true value and must run before masking: afterwards it would overwrite
+    // the masked value with the true one.
+    pub fn set_weight(&mut self, weight: u32) -> Result<(), AggregationError> {
+        if self.interacted {
+            return Err(AggregationError::WrongPhase(Phase::Initialized));
+        }
+        self.weight = weight;
+        self.value = self.value.iter().map(|x| x.wrapping_mul(T::from_u32(weight))).collect();
+        self.masked_value = self.value.clone();
+        Ok(())
+    }
+
+    // Noise goes into the value the client is about to mask, never into its true value.
+    pub fn add_dp_noise(&mut self, noise: Noise, rng: &mut impl Rng) {
+        for masked in self.masked_value.iter_mut() {
+            dp::add_noise(masked, noise, rng);
+        }
+    }
+
+    pub fn add_to_value(&mut self, masking_val: &[T]) {
+        for ((masked, net), mask) in self.masked_value.iter_mut().zip(self.net_mask.iter_mut()).zip(masking_val) {
+            *masked = masked.wrapping_add(*mask);
+            *net = net.wrapping_add(*mask);
+        }
+    }
+
+    pub fn subtract_from_value(&mut self, masking_val: &[T]) {
+        for ((masked, net), mask) in self.masked_value.iter_mut().zip(self.net_mask.iter_mut()).zip(masking_val) {
+            *masked = masked.wrapping_sub(*mask);
+            *net = net.wrapping_sub(*mask);
+        }
+    }
+
+    // Both members of a pair derive the same X25519 shared secret and use it as the
+    // seed of the pairwise mask, so the mask itself never leaves either client.
+    pub fn pairwise_mask(&self, peer_public_key: &PublicKey) -> Vec<T> {
+        pairwise_mask(&self.secret_key, peer_public_key, self.value.len())
+    }
+
+    pub fn pairwise_mask_round(&self, peer_public_key: &PublicKey, round: u32) -> Vec<T> {
+        pairwise_mask_round(&self.secret_key, peer_public_key, self.value.len(), round)
+    }
+
+    pub fn add_self_mask(&mut self) {
+        if self.interacted {
+            return;
+        }
+        let self_mask: Vec<T> = mask::prg_mask(self.self_mask_seed, self.value.len());
+        self.add_to_value(&self_mask);
+    }
+
+    // Checks that the server's commitment covers the masked value this client, as
+    // client number id, submitted.
+    pub fn verify_inclusion(&self, id: usize, root: &Hash, proof: &[ProofStep]) -> bool {
+        commitment::verify_inclusion(root, commitment::leaf_hash(id, &self.masked_value), proof)
+    }
+
+    pub fn sign_survivors(&self, survivors: &[usize]) -> Signature {
+        self.signing_key.sign(&survivors_message(survivors))
+    }
+
+    // A client only takes part in recovery if at least threshold peers signed the very
+    // survivor set the server told it about; a server that tells different clients
+    // different sets cannot collect enough matching signatures.
+    pub fn consistency_check(&self, survivors: &[usize], signatures: &[(usize, Signature)], verifying_keys: &[VerifyingKey], threshold: usize) -> bool {
+        let message = survivors_message(survivors);
+        let agreeing = signatures.iter().filter(|(id, signature)| verifying_keys[*id].verify(&message, signature).is_ok()).count();
+        agreeing >= threshold
+    }
+
+    // Compare this out of band with what a peer received as our public key.
+    pub fn fingerprint(&self) -> Hash {
+        fingerprint(&self.public_key)
+    }
+
+    // Pins the peer's public key for all later masking. With an expected fingerprint,
+    // obtained out of band, a key that does not match is refused and nothing is pinned.
+    pub fn handshake(&mut self, peer_public_key: PublicKey, expected_fingerprint: Option<&Hash>) -> bool {
+        if expected_fingerprint.is_some_and(|expected| *expected != fingerprint(&peer_public_key)) {
+            warn!("peer public key does not match its fingerprint");
+            return false;
+        }
+        self.pinned_peers.insert(peer_public_key);
+        true
+    }
+
+    pub fn has_handshake_with(&self, peer_public_key: &PublicKey) -> bool {
+        self.pinned_peers.contains(peer_public_key)
+    }
+
+    // This client adds the mask it shares with each peer and the peer subtracts it,
+    // so each pair's masks cancel out coordinate by coordinate in the aggregate. Every
+    // peer must have completed a handshake with this client first, or nobody masks.
+    pub fn interact_with_others(&mut self, others: &mut [Client<T>], strategy: &dyn MaskingStrategy<T>, rng: &mut dyn RngCore) -> Result<(), AggregationError> {
+        if self.interacted {
+            debug!("already interacted");
+            return Ok(());
+        }
+        if !others.iter().all(|other| self.handshaken(other)) {
+            return Err(AggregationError::NoHandshake);
+        }
+        self.interacted = true;
+        for other in others.iter_mut() {
+            self.mask_with(other, strategy, 0, rng)?;
+        }
+        Ok(())
+    }
+
+    // Once the whole group has masked, any member can add up the group's masked values
+    // itself: the masks cancel within the group whoever does the adding. Peers are the
+    // other members of this client's group.
+    pub fn group_aggregate(&self, peers: &[Client<T>]) -> Result<Vec<T>, AggregationError> {
+        if !self.interacted || peers.iter().any(|peer| !peer.interacted) {
+            return Err(AggregationError::NotInteracted);
+        }
+        Ok(sum_vectors(std::iter::once(&self.masked_value).chain(peers.iter().map(|peer| &peer.masked_value)), self.value.len()))
+    }
+
+    fn handshaken(&self, other: &Client<T>) -> bool {
+        self.has_handshake_with(&other.public_key) && other.has_handshake_with(&self.public_key)
+    }
+
+    fn mask_with(&mut self, other: &mut Client<T>, strategy: &dyn MaskingStrategy<T>, round: u32, rng: &mut dyn RngCore) -> Result<(), AggregationError> {
+        if !self.handshaken(other) {
+            return Err(AggregationError::NoHandshake);
+        }
+        let masking_val = strategy.round_mask(self, other, round, rng);
+        self.add_to_value(&masking_val);
+        other.subtract_from_value(&masking_val);
+        Ok(())
+    }
+}
+
+// Every unordered pair of ids exactly once, as (earlier, later) in the order given. The
+// first of each pair is the one that adds the mask.
+pub fn pairs(ids: &[usize]) -> Vec<(usize, usize)> {
+    ids.iter().enumerate().flat_map(|(k, &i)| ids[k + 1..].iter().map(move |&j| (i, j))).collect()
+}
+
+// A round needs at least one client and at most max of them.
+pub fn check_participants(num_participants: usize, max: usize) -> Result<(), AggregationError> {
+    if num_participants == 0 || num_participants > max {
+        return Err(AggregationError::InvalidParticipants { requested: num_participants, max });
+    }
+    Ok(())
+}
+
+// Picks the mask that client a adds and client b subtracts. Any mask cancels in the
+// aggregate; what a strategy decides is who can know it.
+pub trait MaskingStrategy<T: MaskableValue> {
+    fn pairwise_mask(&self, a: &Client<T>, b: &Client<T>, rng: &mut dyn RngCore) -> Vec<T>;
+
+    // The mask for one of several masking rounds. A strategy that draws fresh randomness
+    // on every call gets fresh masks per round without overriding this.
+    fn round_mask(&self, a: &Client<T>, b: &Client<T>, _round: u32, rng: &mut dyn RngCore) -> Vec<T> {
+        self.pairwise_mask(a, b, rng)
+    }
+
+    // Whether a dropped client's masks can be rebuilt from its reconstructed secret key.
+    // Only masks derived from the pair's keys can; recovery refuses dropouts otherwise.
+    fn recoverable(&self) -> bool {
+        false
+    }
+}
+
+// The default: the mask is expanded from the pair's X25519 shared secret, which b derives
+// from its own key just as a does. Dropout recovery relies on this, since it rebuilds a
+// dropped client's masks from its reconstructed secret key.
+pub struct SeededPairwise;
+
+impl<T: MaskableValue> MaskingStrategy<T> for SeededPairwise {
+    fn pairwise_mask(&self, a: &Client<T>, b: &Client<T>, _rng: &mut dyn RngCore) -> Vec<T> {
+        a.pairwise_mask(&b.public_key)
+    }
+
+    fn round_mask(&self, a: &Client<T>, b: &Client<T>, round: u32, _rng: &mut dyn RngCore) -> Vec<T> {
+        a.pairwise_mask_round(&b.public_key, round)
+    }
+
+    fn recoverable(&self) -> bool {
+        true
+    }
+}
+
+// Fresh masks drawn by whoever runs the round, as in the original tutorial. The server
+// then knows every mask, and dropped clients' masks cannot be recovered.
+pub struct RandomPairwise;
+
+impl<T: MaskableValue> MaskingStrategy<T> for RandomPairwise {
+    fn pairwise_mask(&self, a: &Client<T>, _b: &Client<T>, mut rng: &mut dyn RngCore) -> Vec<T> {
+        (0..a.value.len()).map(|_| T::random(&mut rng)).collect()
+    }
+}
+
+// SHA-256 of the X25519 public key.
+fn fingerprint(public_key: &PublicKey) -> Hash {
+    Sha256::digest(public_key.as_bytes()).into()
+}
+
+// Masks drawn uniformly from 0..bound instead of the whole type, so masked values in a
+// demo stay readable. They cancel just the same, but a small mask barely hides a value,
+// and like RandomPairwise they cannot be recovered for dropped clients; use the
+// full-range strategies for anything but illustration. The bound must be at least 1.
+pub struct BoundedPairwise {
+    pub bound: u32,
+}
+
+impl<T: MaskableValue> MaskingStrategy<T> for BoundedPairwise {
+    fn pairwise_mask(&self, a: &Client<T>, _b: &Client<T>, rng: &mut dyn RngCore) -> Vec<T> {
+        assert!(self.bound >= 1, "BoundedPairwise needs a bound of at least 1");
+        (0..a.value.len()).map(|_| T::from_u32(rng.gen_range(0..self.bound))).collect()
+    }
+}
+
+fn survivors_message(survivors: &[usize]) -> Vec<u8> {
+    let mut sorted = survivors.to_vec();
+    sorted.sort_unstable();
+    sorted.iter().flat_map(|&id| (id as u32).to_le_bytes()).collect()
+}
+
+fn result_message<T: MaskableValue>(aggregate: &[T]) -> Vec<u8> {
+    aggregate.iter().flat_map(|&x| x.to_le_bytes()).collect()
+}
+
+// True if signature is the server with this key signing exactly this aggregate.
+pub fn verify_result<T: MaskableValue>(verifying_key: &VerifyingKey, aggregate: &[T], signature: &Signature) -> bool {
+    verifying_key.verify(&result_message(aggregate), signature).is_ok()
+}
+
+pub fn pairwise_mask<T: MaskableValue>(secret_key: &StaticSecret, peer_public_key: &PublicKey, dim: usize) -> Vec<T> {
+    let shared_secret = secret_key.diffie_hellman(peer_public_key);
+    mask::prg_mask(shared_secret.to_bytes(), dim)
+}
+
+// Round r's mask is the r-th block of dim values in the pair's PRG stream, so round 0
+// is the single-round mask and every later round gets a fresh one.
+pub fn pairwise_mask_round<T: MaskableValue>(secret_key: &StaticSecret, peer_public_key: &PublicKey, dim: usize, round: u32) -> Vec<T> {
+    let mut stream: Vec<T> = pairwise_mask(secret_key, peer_public_key, dim * (round as usize + 1));
+    stream.split_off(dim * round as usize)
+}
+
+// Sum of a pair's masks over the first rounds rounds.
+fn pairwise_mask_rounds<T: MaskableValue>(secret_key: &StaticSecret, peer_public_key: &PublicKey, dim: usize, rounds: u32) -> Vec<T> {
+    let stream: Vec<T> = pairwise_mask(secret_key, peer_public_key, dim * rounds as usize);
+    let blocks: Vec<Vec<T>> = stream.chunks(dim.max(1)).map(<[T]>::to_vec).collect();
+    sum_vectors(blocks.iter(), dim)
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Server<T: MaskableValue> {
+    pub clients: Vec<Client<T>>,
+    dim: usize,
+    aggregate_value: Vec<T>,
+    // Merkle tree over the masked values of the clients in committed, in that order.
+    commitment: MerkleTree,
+    committed: Vec<usize>,
+    phase: Phase,
+    // streams[k] names coordinate k of every value; empty unless name_streams was called.
+    streams: Vec<String>,
+    // Largest value the clients' distribution can produce.
+    max_value: u32,
+    clip_bound: Option<u32>,
+    max_reasonable_aggregate: Option<u128>,
+    group_size: usize,
+    shuffle_peers: bool,
+    masking_rounds: u32,
+    interaction_order: Vec<(usize, usize)>,
+    // False once any pair masked with a strategy whose masks recovery cannot rebuild.
+    recoverable: bool,
+    double_masked: bool,
+    threshold: usize,
+    // Signs aggregates so consumers can tell they came from this server.
+    signing_key: SigningKey,
+    // Not saved: a loaded server draws fresh randomness.
+    #[serde(skip, default = "StdRng::from_entropy")]
+    rng: StdRng,
+}
+
+impl<T: MaskableValue> Server<T> {
+    // Panics unless 1 <= num_participants <= MAX_PARTICIPANTS.
+    pub fn with_rng(rng: StdRng, num_participants: usize, dim: usize) -> Server<T> {
+        Server::with_dist(rng, ValueDist::default(), num_participants, dim)
+    }
+
+    pub fn with_dist(mut rng: StdRng, dist: ValueDist, num_participants: usize, dim: usize) -> Server<T> {
+        let clients = Client::initialize_multiple_with(dist, num_participants, dim, &mut rng).unwrap_or_else(|err| panic!("{}", err));
+        let signing_key = SigningKey::generate(&mut rng);
+        Server { clients, dim, aggregate_value: vec![T::zero(); dim], commitment: MerkleTree::new(Vec::new()), committed: Vec::new(), phase: Phase::Idle, streams: Vec::new(), max_value: dist.max(), clip_bound: None, max_reasonable_aggregate: None, group_size: num_participants, shuffle_peers: false, masking_rounds: 1, interaction_order: Vec::new(), recoverable: true, double_masked: false, threshold: 0, signing_key, rng }
+    }
+
+    // Same seed, same client values and masks: use this to reproduce a run.
+    pub fn with_seed(seed: u64, num_participants: usize, dim: usize) -> Server<T> {
+        Server::with_rng(StdRng::seed_from_u64(seed), num_participants, dim)
+    }
+
+    pub fn new_random(num_participants: usize, dim: usize) -> Server<T> {
+        Server::with_rng(StdRng::from_entropy(), num_participants, dim)
+    }
+
+    // Clips every client to the bound, which caps what an honest client can contribute.
+    pub fn clip(&mut self, max: u32) -> Result<(), AggregationError> {
+        if self.phase != Phase::Idle {
+            return Err(AggregationError::WrongPhase(self.phase));
+        }
+        self.clip_bound = Some(max);
+        for client in self.clients.iter_mut() {
+            client.clip(max)?;
+        }
+        Ok(())
+    }
+
+    // weights[i] is the weight of client i; see Client::set_weight.
+    pub fn set_weights(&mut self, weights: &[u32]) -> Result<(), AggregationError> {
+        assert_eq!(weights.len(), self.clients.len(), "need one weight per client");
+        if self.phase != Phase::Idle {
+            return Err(AggregationError::WrongPhase(self.phase));
+        }
+        for (client, &weight) in self.clients.iter_mut().zip(weights) {
+            client.set_weight(weight)?;
+        }
+        Ok(())
+    }
+
+    // Divisor that turns the weighted aggregate into a weighted mean.
+    pub fn total_weight(&self) -> u64 {
+        self.survivors().iter().map(|&v| self.clients[v].weight as u64).sum()
+    }
+
+    // Bound on each coordinate of the aggregate when every client is honest: the total
+    // weight times the clip bound, or times the largest value a client draws when nothing
+    // is clipped. Without weights the total weight is just n.
+    pub fn max_contribution(&self) -> u128 {
+        let total_weight: u128 = self.clients.iter().map(|client| client.weight as u128).sum();
+        self.clip_bound.unwrap_or(self.max_value) as u128 * total_weight
+    }
+
+    // Overrides max_contribution as the largest aggregate is_anomalous accepts.
+    pub fn set_max_reasonable_aggregate(&mut self, max: u128) {
+        self.max_reasonable_aggregate = Some(max);
+    }
+
+    // The server never sees a true value, but an aggregate above what honest clients can
+    // produce means at least one of them sent something it should not have.
+    pub fn is_anomalous(&self) -> bool {
+        let bound = self.max_reasonable_aggregate.unwrap_or_else(|| self.max_contribution());
+        self.aggregate_value.iter().any(|x| x.to_f64() > bound as f64)
+    }
+
+    // Must run before initialize so that each client perturbs its own value, not its masked one.
+    pub fn add_dp_noise(&mut self, noise: Noise) {
+        for client in self.clients.iter_mut() {
+            client.add_dp_noise(noise, &mut self.rng);
+        }
+    }
+
+    // Client i only interacts with clients j > i, so every unordered pair exchanges exactly one mask.
+    // A lone client has no peers and sends its true value, which makes a one-client round
+    // a sanity check of the plumbing; in double-masking mode its self-mask still hides it.
+    pub fn initialize(&mut self) -> Result<(), AggregationError> {
+        self.initialize_with(&SeededPairwise)
+    }
+
+    pub fn initialize_with(&mut self, strategy: &dyn MaskingStrategy<T>) -> Result<(), AggregationError> {
+        self.initialize_grouped_with(self.clients.len().max(1), strategy)
+    }
+
+    // Bonawitz-style double masking: each client also adds a self-mask before the pairwise
+    // masks. Recovery removes the self-masks of survivors and the pairwise masks of dropped
+    // clients, and no client ever has both of its secrets reconstructed.
+    pub fn initialize_double_masked(&mut self) -> Result<(), AggregationError> {
+        if self.phase == Phase::Aggregated {
+            return Err(AggregationError::WrongPhase(self.phase));
+        }
+        self.double_masked = true;
+        for client in self.clients.iter_mut() {
+            client.add_self_mask();
+        }
+        self.initialize()
+    }
+
+    // Only clients within the same run of group_size consecutive clients mask with each
+    // other, cutting the work from O(n^2) to O(n * group_size). Masks still cancel inside
+    // every group, so the aggregate is unchanged, but each client now hides only among
+    // its group_size - 1 peers.
+    pub fn initialize_grouped(&mut self, group_size: usize) -> Result<(), AggregationError> {
+        self.initialize_grouped_with(group_size, &SeededPairwise)
+    }
+
+    // Two clients with one public key would share every pairwise mask with their peers,
+    // so each would learn what the other hides; clients built from the same seeded rng
+    // end up like this.
+    pub fn initialize_grouped_with(&mut self, group_size: usize, strategy: &dyn MaskingStrategy<T>) -> Result<(), AggregationError> {
+        assert!(group_size >= 1, "group_size must be at least 1");
+        if self.phase == Phase::Aggregated {
+            return Err(AggregationError::WrongPhase(self.phase));
+        }
+        let num_clients = self.clients.len();
+        let mut public_keys = HashSet::with_capacity(num_clients);
+        for (i, client) in self.clients.iter().enumerate() {
+            if !public_keys.insert(client.public_key.to_bytes()) {
+                return Err(AggregationError::DuplicateClient(i as u32));
+            }
+        }
+        self.group_size = group_size;
+        let mut pairs = Vec::new();
+        for start in (0..num_clients).step_by(group_size) {
+            let group: Vec<usize> = (start..(start + group_size).min(num_clients)).collect();
+            pairs.extend(self::pairs(&group).into_iter().filter(|&(i, _)| !self.clients[i].interacted));
+        }
+        if self.shuffle_peers {
+            pairs.shuffle(&mut self.rng);
+        }
+        if !pairs.is_empty() {
+            self.recoverable &= strategy.recoverable();
+        }
+        self.mask_pairs(pairs, strategy);
+        for client in self.clients.iter_mut() {
+            client.interacted = true;
+        }
+        self.phase = Phase::Initialized;
+        Ok(())
+    }
+
+    // Each pair completes a handshake and masks once per masking round. The lower index
+    // always adds, so recovery does not depend on the order.
+    fn mask_pairs(&mut self, pairs: Vec<(usize, usize)>, strategy: &dyn MaskingStrategy<T>) {
+        for &(i, j) in &pairs {
+            let (left, right) = self.clients.split_at_mut(j);
+            left[i].handshake(right[0].public_key, None);
+            right[0].handshake(left[i].public_key, None);
+            for round in 0..self.masking_rounds {
+                left[i].mask_with(&mut right[0], strategy, round, &mut self.rng).expect("the pair just completed a handshake");
+            }
+            debug!(client = i, peer = j, "interacted");
+        }
+        self.interaction_order.extend(pairs);
+    }
+
+    // Names the coordinates of every client's value, so several statistics, a sum and a
+    // count say, aggregate side by side. Each coordinate takes its own block of the pair's
+    // PRG stream as its mask, so the streams are masked independently.
+    pub fn name_streams(&mut self, names: &[&str]) {
+        assert_eq!(names.len(), self.dim, "need one stream name per coordinate");
+        self.streams = names.iter().map(|name| name.to_string()).collect();
+    }
+
+    // Sets a client's value stream by stream; streams it has no value for are zero. Like
+    // clip, this must run before masking.
+    pub fn set_stream_values(&mut self, client: usize, values: &HashMap<String, T>) -> Result<(), AggregationError> {
+        for name in values.keys() {
+            assert!(self.streams.contains(name), "unknown stream {}", name);
+        }
+        if self.phase != Phase::Idle {
+            return Err(AggregationError::WrongPhase(self.phase));
+        }
+        let value: Vec<T> = self.streams.iter().map(|name| values.get(name).copied().unwrap_or_else(T::zero)).collect();
+        self.clients[client].masked_value = value.clone();
+        self.clients[client].value = value;
+        Ok(())
+    }
+
+    // The aggregate of every named stream.
+    pub fn aggregate_streams(&mut self) -> Result<HashMap<String, T>, AggregationError> {
+        let aggregate = self.aggregate()?;
+        Ok(self.streams.iter().cloned().zip(aggregate).collect())
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    // Masks pairs in a random order instead of client by client, so the order in which a
+    // client's masks are applied says nothing about who its peers are.
+    pub fn shuffle_peers(&mut self) {
+        self.shuffle_peers = true;
+    }
+
+    // Repeats every pair's masking rounds times, each round with a fresh mask. Each
+    // round's masks cancel on their own, so the aggregate does not depend on rounds.
+    pub fn set_masking_rounds(&mut self, rounds: u32) {
+        assert!(rounds >= 1, "need at least one masking round");
+        self.masking_rounds = rounds;
+    }
+
+    // Every (adding client, subtracting client) pair in the order they were masked.
+    pub fn interaction_order(&self) -> &[(usize, usize)] {
+        &self.interaction_order
+    }
+
+    // Lets a client that was not ready at initialize join the round before aggregation.
+    // Every existing client adds the mask it shares with the newcomer, who subtracts it,
+    // matching the lower-index-adds convention recovery relies on. Only a single group
+    // can grow this way, and the newcomer holds no key shares, so join before share_keys.
+    // In a double-masked round the newcomer adds its self-mask like everyone else.
+    pub fn join(&mut self, value: Vec<T>) -> Result<usize, AggregationError> {
+        if self.phase != Phase::Initialized {
+            return Err(AggregationError::WrongPhase(self.phase));
+        }
+        if self.group_size < self.clients.len() || self.threshold > 0 {
+            return Err(AggregationError::CannotJoin);
+        }
+        let mut client = Client::new(value, &mut self.rng);
+        if let Some(max) = self.clip_bound {
+            client.clip(max)?;
+        }
+        if self.double_masked {
+            client.add_self_mask();
+        }
+        self.clients.push(client);
+        let newcomer = self.clients.len() - 1;
+        let ids: Vec<usize> = (0..self.clients.len()).collect();
+        let pairs = pairs(&ids).into_iter().filter(|&(_, j)| j == newcomer).collect();
+        self.mask_pairs(pairs, &SeededPairwise);
+        self.clients[newcomer].interacted = true;
+        self.group_size = self.group_size.max(self.clients.len());
+        debug!(client = newcomer, peers = newcomer, "joined");
+        Ok(newcomer)
+    }
+
+    // Every client splits its secret key and self-mask seed into one Shamir share per
+    // client, so any threshold of the survivors can later rebuild either of them.
+    pub fn share_keys(&mut self, threshold: usize) {
+        self.threshold = threshold;
+        let num_clients = self.clients.len();
+        for i in 0..num_clients {
+            let key_shares = secret_sharing::split(self.clients[i].secret_key.to_bytes(), num_clients, threshold, &mut self.rng);
+            let self_mask_shares = secret_sharing::split(self.clients[i].self_mask_seed, num_clients, threshold, &mut self.rng);
+            for ((client, key_share), self_mask_share) in self.clients.iter_mut().zip(key_shares).zip(self_mask_shares) {
+                client.key_shares.push(key_share);
+                client.self_mask_shares.push(self_mask_share);
+            }
+        }
+    }
+
+    // Marks a random dropout_rate fraction of the clients as dropped: they never submit
+    // their masked values, leaving their half of every pairwise mask uncancelled.
+    pub fn simulate_dropout(&mut self, dropout_rate: f64) {
+        let num_dropped = (self.clients.len() as f64 * dropout_rate).round() as usize;
+        for i in index::sample(&mut self.rng, self.clients.len(), num_dropped) {
+            self.clients[i].dropped = true;
+        }
+    }
+
+    pub fn survivors(&self) -> Vec<usize> {
+        (0..self.clients.len()).filter(|&i| !self.clients[i].dropped).collect()
+    }
+
+    // Diagnostic for the protocol invariant: pairwise masks sum to zero over all clients.
+    // Self-masks in double-masking mode are the only masks that legitimately remain.
+    pub fn net_mask(&self) -> Vec<T> {
+        sum_vectors(self.clients.iter().map(|client| &client.net_mask), self.dim)
+    }
+
+    // Every mask the clients applied, summed, minus the self-masks that double masking
+    // leaves in on purpose. All zeros unless some pairwise mask failed to cancel.
+    pub fn mask_residual(&self) -> Vec<T> {
+        let mut residual = self.net_mask();
+        if self.double_masked {
+            for client in &self.clients {
+                let self_mask: Vec<T> = mask::prg_mask(client.self_mask_seed, self.dim);
+                residual = residual.iter().zip(&self_mask).map(|(r, m)| r.wrapping_sub(*m)).collect();
+            }
+        }
+        residual
+    }
+
+    // The operator-facing form of the invariant behind the aggregate: reads every
+    // client's masks, which a real server never could, and reports whether they cancel.
+    pub fn verify_masks(&self) -> bool {
+        let residual = self.mask_residual();
+        let cancelled = residual.iter().all(|m| m.to_f64() == 0.0);
+        if cancelled {
+            info!("masks cancelled ✓");
+        } else {
+            warn!(residual = ?residual.iter().map(|m| m.to_f64()).collect::<Vec<f64>>(), "masks did not cancel");
+        }
+        cancelled
+    }
+
+    // The survivors' submitted values summed exactly in a u128 accumulator, outside the
+    // masked domain: each client's masked value minus its net mask, so DP noise counts and
+    // masks do not. The masked sum only ever carries the total modulo T's modulus, so this
+    // reference path is the only place the true sum exists. None if some value reads as
+    // half the modulus or more, since that is as likely a negative number (DP noise, a
+    // quantized negative) as a huge positive one.
+    pub fn reference_sum(&self) -> Option<Vec<u128>> {
+        let half_modulus = (T::modulus() / 2) as i128;
+        let mut sum = vec![0; self.dim];
+        for &v in &self.survivors() {
+            let client = &self.clients[v];
+            for ((total, masked), mask) in sum.iter_mut().zip(&client.masked_value).zip(&client.net_mask) {
+                let value = masked.wrapping_sub(*mask).to_i128();
+                if !(0..half_modulus).contains(&value) {
+                    return None;
+                }
+                *total += value as u128;
+            }
+        }
+        Some(sum)
+    }
+
+    // True if the aggregate is the true sum reduced modulo T's modulus rather than the
+    // true sum itself, which max_contribution cannot see when values were set outside the
+    // distribution the server was built with. An aggregate that does not even agree with
+    // the true sum modulo the modulus has uncancelled masks, not a wrap, and a true sum
+    // that cannot be read unambiguously is never reported as wrapped.
+    pub fn aggregate_wrapped(&self) -> bool {
+        let Some(reference) = self.reference_sum() else {
+            return false;
+        };
+        let modulus = T::modulus() as i128;
+        reference.iter().zip(&self.aggregate_value).any(|(&reference, x)| {
+            let reference = reference as i128;
+            reference.rem_euclid(modulus) == x.to_i128().rem_euclid(modulus) && reference != x.to_i128()
+        })
+    }
+
+    fn check_submissions(&self, survivors: &[usize]) -> Result<(), AggregationError> {
+        if self.phase == Phase::Idle {
+            return Err(AggregationError::NotInitialized);
+        }
+        for &v in survivors {
+            let got = self.clients[v].masked_value.len();
+            if got != self.dim {
+                return Err(AggregationError::DimensionMismatch { expected: self.dim, got });
+            }
+        }
+        // Masks cancel modulo the modulus, so a true sum at or above it wraps silently.
+        let max_contribution = self.max_contribution();
+        if max_contribution >= T::modulus() {
+            warn!(max_contribution, modulus = T::modulus(), "the aggregate can overflow the value type");
+            return Err(AggregationError::Overflow);
+        }
+        Ok(())
+    }
+
+    fn commit(&mut self, survivors: &[usize]) {
+        self.commitment = MerkleTree::new(survivors.iter().map(|&v| commitment::leaf_hash(v, &self.clients[v].masked_value)).collect());
+        self.committed = survivors.to_vec();
+    }
+
+    // Root of the Merkle tree over the contributions in the last aggregate.
+    pub fn commitment_root(&self) -> Hash {
+        self.commitment.root()
+    }
+
+    // None if the client's masked value was not part of the last aggregate.
+    pub fn inclusion_proof(&self, client: usize) -> Option<Vec<ProofStep>> {
+        self.committed.iter().position(|&v| v == client).map(|leaf| self.commitment.proof(leaf))
+    }
+
+    pub fn verifying_key(&self) -> VerifyingKey {
+        self.signing_key.verifying_key()
+    }
+
+    // Signature over the last aggregate; check it with verify_result.
+    pub fn sign_result(&self) -> Signature {
+        self.signing_key.sign(&result_message(&self.aggregate_value))
+    }
+
+    // Element-wise sum of the masked vectors the surviving clients submitted. Self-masks
+    // only come off with the survivors' shares, so a double-masked round is recovered.
+    pub fn aggregate(&mut self) -> Result<Vec<T>, AggregationError> {
+        if self.double_masked {
+            return self.aggregate_with_recovery();
+        }
+        let survivors = self.survivors();
+        self.check_submissions(&survivors)?;
+        if survivors.len() == self.clients.len() {
+            debug_assert!(self.net_mask().iter().all(|m| m.to_f64() == 0.0), "pairwise masks did not cancel");
+        }
+        self.commit(&survivors);
+        self.aggregate_value = sum_vectors(survivors.iter().map(|&v| &self.clients[v].masked_value), self.dim);
+        self.phase = Phase::Aggregated;
+        if self.aggregate_wrapped() {
+            warn!("the true sum exceeds the value type's modulus and the aggregate wrapped");
+        }
+        if self.is_anomalous() {
+            warn!("the aggregate exceeds what honest clients can contribute");
+        }
+        Ok(self.aggregate_value.clone())
+    }
+
+    // The aggregate divided by the total weight of the survivors, which is simply their
+    // number unless weights were set.
+    pub fn aggregate_mean(&mut self) -> Result<Vec<f64>, AggregationError> {
+        let total_weight = self.total_weight();
+        if total_weight == 0 {
+            return Err(AggregationError::NoContributors);
+        }
+        Ok(self.aggregate()?.into_iter().map(|x| x.to_f64() / total_weight as f64).collect())
+    }
+
+    // Honest run of the consistency check: every survivor is told the same survivor set.
+    pub fn consistency_check(&self) -> Result<(), AggregationError> {
+        let survivors = self.survivors();
+        self.consistency_check_with(&vec![survivors.clone(); survivors.len()])
+    }
+
+    // reported[k] is the survivor set the server claims to the k-th survivor. Each survivor
+    // signs what it was told, and aborts unless threshold peers signed the same set.
+    pub fn consistency_check_with(&self, reported: &[Vec<usize>]) -> Result<(), AggregationError> {
+        let survivors = self.survivors();
+        assert_eq!(reported.len(), survivors.len(), "need one reported survivor set per survivor");
+        let signatures: Vec<(usize, Signature)> = survivors.iter().zip(reported).map(|(&v, set)| (v, self.clients[v].sign_survivors(set))).collect();
+        let verifying_keys: Vec<VerifyingKey> = self.clients.iter().map(|client| client.verifying_key).collect();
+        for (&v, set) in survivors.iter().zip(reported) {
+            if !self.clients[v].consistency_check(set, &signatures, &verifying_keys, self.threshold) {
+                return Err(AggregationError::InconsistentSurvivors(v as u32));
+            }
+        }
+        Ok(())
+    }
+
+    // Sums the survivors' masked vectors, then rebuilds each dropped client's secret key
+    // from threshold survivor shares and cancels the masks it shared with the survivors.
+    // In double-masking mode it also rebuilds and removes every survivor's self-mask.
+    pub fn aggregate_with_recovery(&mut self) -> Result<Vec<T>, AggregationError> {
+        let survivors = self.survivors();
+        let dropped: Vec<usize> = (0..self.clients.len()).filter(|&i| self.clients[i].dropped).collect();
+        self.check_submissions(&survivors)?;
+        // Without share_keys there is nothing to rebuild a dropped client's key from.
+        if let (Some(&d), 0) = (dropped.first(), self.threshold) {
+            return Err(AggregationError::MissingClient(d as u32));
+        }
+        // Nor can masks that were not derived from the pair's keys be rebuilt at all.
+        if let (Some(&d), false) = (dropped.first(), self.recoverable) {
+            return Err(AggregationError::MissingClient(d as u32));
+        }
+        // Checked before the consistency check, which would otherwise abort on the same
+        // shortage with a less useful error.
+        let enough_shares = self.threshold > 0 && survivors.len() >= self.threshold;
+        if (self.double_masked || !dropped.is_empty()) && !enough_shares {
+            return Err(AggregationError::NotEnoughShares { threshold: self.threshold, survivors: survivors.len() });
+        }
+        self.consistency_check()?;
+        self.commit(&survivors);
+        let mut aggregate = sum_vectors(survivors.iter().map(|&v| &self.clients[v].masked_value), self.dim);
+        if self.double_masked {
+            for &u in &survivors {
+                let shares: Vec<Share> = survivors.iter().take(self.threshold).map(|&v| self.clients[v].self_mask_shares[u]).collect();
+                let self_mask: Vec<T> = mask::prg_mask(secret_sharing::reconstruct(&shares), self.dim);
+                aggregate = aggregate.iter().zip(&self_mask).map(|(a, m)| a.wrapping_sub(*m)).collect();
+            }
+        }
+        for &d in &dropped {
+            let shares: Vec<Share> = survivors.iter().take(self.threshold).map(|&v| self.clients[v].key_shares[d]).collect();
+            let secret_key = StaticSecret::from(secret_sharing::reconstruct(&shares));
+            for &v in survivors.iter().filter(|&&v| v / self.group_size == d / self.group_size) {
+                let mask: Vec<T> = pairwise_mask_rounds(&secret_key, &self.clients[v].public_key, self.dim, self.masking_rounds);
+                // The lower index added the pair's mask, the higher one subtracted it.
+                let correction = if d < v { mask } else { mask.iter().map(|m| T::zero().wrapping_sub(*m)).collect() };
+                aggregate = sum_vectors([aggregate, correction].iter(), self.dim);
+            }
+        }
+        self.aggregate_value = aggregate;
+        self.phase = Phase::Aggregated;
+        if self.aggregate_wrapped() {
+            warn!("the true sum exceeds the value type's modulus and the aggregate wrapped");
+        }
+        if self.is_anomalous() {
+            warn!("the aggregate exceeds what honest clients can contribute");
+        }
+        Ok(self.aggregate_value.clone())
+    }
+}
+
+// Masks every pair of clients with no server in between: each client pins all of its
+// peers' keys, then every pair masks once, the earlier client adding.
+pub fn mask_peer_to_peer<T: MaskableValue>(clients: &mut [Client<T>], rng: &mut dyn RngCore) {
+    let ids: Vec<usize> = (0..clients.len()).collect();
+    let pairs = pairs(&ids);
+    for &(i, j) in &pairs {
+        let (left, right) = clients.split_at_mut(j);
+        left[i].handshake(right[0].public_key, None);
+        right[0].handshake(left[i].public_key, None);
+    }
+    // A client that already masked, in an earlier call, does not mask again.
+    let pending: Vec<(usize, usize)> = pairs.into_iter().filter(|&(i, _)| !clients[i].interacted).collect();
+    for (i, j) in pending {
+        let (left, right) = clients.split_at_mut(j);
+        left[i].mask_with(&mut right[0], &SeededPairwise, 0, rng).expect("every pair just completed a handshake");
+    }
+    for client in clients.iter_mut() {
+        client.interacted = true;
+    }
+}
+
+// Coordinator-free aggregation: the clients form a ring and pass a running sum along it,
+// each adding its masked value, so the last client ends up holding the aggregate. Every
+// partial sum still carries the masks shared with clients further along the ring.
+pub fn ring_aggregate<T: MaskableValue>(clients: &[Client<T>]) -> Vec<T> {
+    let dim = clients.first().map_or(0, |client| client.masked_value.len());
+    clients.iter().enumerate().fold(vec![T::zero(); dim], |partial, (i, client)| {
+        debug!(client = i, "passing the partial sum on");
+        partial.iter().zip(&client.masked_value).map(|(a, b)| a.wrapping_add(*b)).collect()
+    })
+}
+
+impl<T: MaskableValue + Serialize + DeserializeOwned> Server<T> {
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string(self)
+    }
+
+    pub fn from_json(json: &str) -> serde_json::Result<Server<T>> {
+        serde_json::from_str(json)
+    }
+
+    // Snapshots the whole round, secret keys and shares included, so keep the file as
+    // private as the clients' keys themselves.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_json()?)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Server<T>> {
+        Ok(Server::from_json(&fs::read_to_string(path)?)?)
+    }
+}
+
+pub fn sum_vectors<'a, T: MaskableValue + 'a>(vectors: impl Iterator<Item = &'a Vec<T>>, dim: usize) -> Vec<T> {
+    vectors.fold(vec![T::zero(); dim], |sum, vector| sum.iter().zip(vector).map(|(a, b)| a.wrapping_add(*b)).collect())
+}
+
+impl Server<Wrapping<u32>> {
+    // For clients holding quantized values: aggregates, then decodes each coordinate
+    // into the mean over the clients that contributed.
+    pub fn aggregate_dequantized(&mut self, scale: f64) -> Result<Vec<f64>, AggregationError> {
+        let num_contributors = self.survivors().len() as u32;
+        Ok(self.aggregate()?.into_iter().map(|q| quant::dequantize(q, scale, num_contributors)).collect())
+    }
+}
+
+impl Server<Wrapping<i64>> {
+    // Signed counterpart of the Wrapping<u32> version, for values from quant::quantize_i64.
+    pub fn aggregate_dequantized(&mut self, scale: f64) -> Result<Vec<f64>, AggregationError> {
+        let num_contributors = self.survivors().len() as u32;
+        Ok(self.aggregate()?.into_iter().map(|q| quant::dequantize_i64(q, scale, num_contributors)).collect())
+    }
+}
@@ -0,0 +1,72 @@
+use rand::Rng;
+use std::num::Wrapping;
+
+// A value the protocol can mask: masks are drawn uniformly from the whole type and cancel
+// out because addition and subtraction wrap around its modulus (2^bits, or a prime P).
+pub trait MaskableValue: Copy {
+    fn zero() -> Self;
+    fn from_u32(x: u32) -> Self;
+    fn from_i64(x: i64) -> Self;
+    fn to_f64(self) -> f64;
+    // Exact, unlike to_f64; i128 holds every u64 and i64 value.
+    fn to_i128(self) -> i128;
+    // Number of distinct values, i.e. the modulus that addition wraps around.
+    fn modulus() -> u128;
+    fn random(rng: &mut impl Rng) -> Self;
+    fn wrapping_add(self, other: Self) -> Self;
+    fn wrapping_sub(self, other: Self) -> Self;
+    fn wrapping_mul(self, other: Self) -> Self;
+    fn to_le_bytes(self) -> Vec<u8>;
+}
+
+macro_rules! impl_maskable_value {
+    ($($t:ty),*) => {$(
+        impl MaskableValue for Wrapping<$t> {
+            fn zero() -> Self {
+                Wrapping(0)
+            }
+
+            fn from_u32(x: u32) -> Self {
+                Wrapping(x as $t)
+            }
+
+            fn from_i64(x: i64) -> Self {
+                Wrapping(x as $t)
+            }
+
+            fn to_f64(self) -> f64 {
+                self.0 as f64
+            }
+
+            fn to_i128(self) -> i128 {
+                self.0 as i128
+            }
+
+            fn modulus() -> u128 {
+                1 << <$t>::BITS
+            }
+
+            fn random(rng: &mut impl Rng) -> Self {
+                Wrapping(rng.gen())
+            }
+
+            fn wrapping_add(self, other: Self) -> Self {
+                self + other
+            }
+
+            fn wrapping_sub(self, other: Self) -> Self {
+                self - other
+            }
+
+            fn wrapping_mul(self, other: Self) -> Self {
+                self * other
+            }
+
+            fn to_le_bytes(self) -> Vec<u8> {
+                self.0.to_le_bytes().to_vec()
+            }
+        }
+    )*};
+}
+
+impl_maskable_value!(u32, u64, i64);
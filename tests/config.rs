@@ -0,0 +1,52 @@
+use clap::CommandFactory;
+use secure_aggregation_tutorial::config::{self, FileConfig};
+use secure_aggregation_tutorial::experiment::{Config, Mode, Output};
+use std::env;
+use std::fs;
+
+#[test]
+fn parses_a_sample_file() {
+    let file = FileConfig::parse("mode = \"double-mask\"\nparticipants = 40\ndropout_rate = 0.25\n").unwrap();
+    assert_eq!(file.mode, Some(Mode::DoubleMask));
+    assert_eq!(file.participants, Some(40));
+    assert_eq!(file.dropout_rate, Some(0.25));
+    assert!(file.seed.is_none() && file.dim.is_none() && file.sigma.is_none() && file.output.is_none());
+    assert!(FileConfig::parse("participants = \"forty\"").is_err());
+}
+
+#[test]
+fn file_fills_options_missing_from_the_command_line() {
+    let path = env::temp_dir().join(format!("secagg-{}.toml", std::process::id()));
+    fs::write(&path, "mode = \"dp\"\nparticipants = 40\nseed = 3\noutput = \"json\"\n").unwrap();
+    let path = path.to_str().unwrap();
+    let matches = Config::command().get_matches_from(["secagg", "--config", path, "--participants", "12"]);
+    let config = config::from_matches(&matches).unwrap();
+    assert_eq!((config.mode, config.participants, config.seed, config.output), (Mode::Dp, 12, Some(3), Output::Json));
+    assert_eq!((config.dim, config.dropout_rate, config.sigma), (1, 0.1, 2.0));
+    fs::remove_file(path).unwrap();
+
+    let matches = Config::command().get_matches_from(["secagg", "--config", path]);
+    assert!(config::from_matches(&matches).is_err());
+}
+
+#[test]
+fn rejects_out_of_range_values_from_the_file() {
+    let path = env::temp_dir().join(format!("secagg-range-{}.toml", std::process::id()));
+    fs::write(&path, "mode = \"double-mask\"\nparticipants = 300\n").unwrap();
+    let matches = Config::command().get_matches_from(["secagg", "--config", path.to_str().unwrap()]);
+    assert!(config::from_matches(&matches).unwrap_err().contains("300 participants"));
+
+    fs::write(&path, "dropout_rate = 2.0\n").unwrap();
+    let matches = Config::command().get_matches_from(["secagg", "--config", path.to_str().unwrap()]);
+    assert!(config::from_matches(&matches).unwrap_err().contains("dropout rate"));
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn rejects_zero_masking_rounds_from_the_file() {
+    let path = env::temp_dir().join(format!("secagg-rounds-{}.toml", std::process::id()));
+    fs::write(&path, "masking_rounds = 0\n").unwrap();
+    let matches = Config::command().get_matches_from(["secagg", "--config", path.to_str().unwrap()]);
+    assert_eq!(config::from_matches(&matches).unwrap_err(), "need at least one masking round");
+    fs::remove_file(path).unwrap();
+}
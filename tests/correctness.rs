@@ -0,0 +1,717 @@
+use rand::{rngs::StdRng, Rng, RngCore, SeedableRng};
+use secure_aggregation_tutorial::dp::Noise;
+use secure_aggregation_tutorial::{check_participants, mask, mask_peer_to_peer, pairs, quant, ring_aggregate, sum_vectors, verify_result, AggregationError, BoundedPairwise, Client, MaskableValue, MaskingStrategy, Phase, RandomPairwise, Server, MAX_PARTICIPANTS};
+use std::collections::{HashMap, HashSet};
+use std::num::Wrapping;
+
+// For each seed, a server with a seed-derived number of participants must aggregate to the
+// naive sum. A failure names the seed so the run can be replayed with Server::with_seed.
+#[test]
+fn secure_aggregate_matches_naive_sum() {
+    for seed in 0..100 {
+        let num_participants = StdRng::seed_from_u64(seed).gen_range(2..=200);
+        let mut server: Server<Wrapping<u32>> = Server::with_seed(seed, num_participants, 1);
+        let naive_aggregate = sum_vectors(server.clients.iter().map(|client| &client.value), 1);
+        server.initialize().unwrap();
+        assert_eq!(server.aggregate().unwrap(), naive_aggregate, "mismatch for seed {} with {} participants", seed, num_participants);
+    }
+}
+
+#[test]
+fn servers_with_the_same_seed_reproduce_each_other() {
+    let mut first: Server<Wrapping<u32>> = Server::with_seed(22, 40, 3);
+    let mut second: Server<Wrapping<u32>> = Server::with_seed(22, 40, 3);
+    let values = |server: &Server<Wrapping<u32>>| server.clients.iter().map(|client| client.value.clone()).collect::<Vec<_>>();
+    assert_eq!(values(&first), values(&second));
+    first.initialize().unwrap();
+    second.initialize().unwrap();
+    assert_eq!(first.aggregate().unwrap(), second.aggregate().unwrap());
+
+    let other: Server<Wrapping<u32>> = Server::with_seed(23, 40, 3);
+    assert_ne!(values(&first), values(&other));
+}
+
+#[test]
+fn a_thousand_clients_aggregate_to_the_naive_sum() {
+    let mut server: Server<Wrapping<u32>> = Server::with_seed(27, 1000, 1);
+    let naive_aggregate = sum_vectors(server.clients.iter().map(|client| &client.value), 1);
+    server.initialize().unwrap();
+    assert_eq!(server.aggregate().unwrap(), naive_aggregate);
+}
+
+#[test]
+fn single_client_aggregates_to_its_own_value() {
+    let mut server: Server<Wrapping<u32>> = Server::with_seed(3, 1, 4);
+    let value = server.clients[0].value.clone();
+    server.initialize().unwrap();
+    assert_eq!(server.clients[0].masked_value, value);
+    assert_eq!(server.aggregate().unwrap(), value);
+
+    let mut self_masked: Server<Wrapping<u32>> = Server::with_seed(3, 1, 4);
+    self_masked.share_keys(1);
+    self_masked.initialize_double_masked().unwrap();
+    assert_ne!(self_masked.clients[0].masked_value, value);
+    assert_eq!(self_masked.aggregate_with_recovery().unwrap(), value);
+}
+
+#[test]
+fn vector_values_aggregate_element_wise() {
+    let mut server: Server<Wrapping<u32>> = Server::with_seed(24, 50, 10);
+    let naive_aggregate: Vec<Wrapping<u32>> = (0..10).map(|k| server.clients.iter().map(|client| client.value[k]).sum()).collect();
+    server.initialize().unwrap();
+    let aggregate = server.aggregate().unwrap();
+    assert_eq!(aggregate.len(), 10);
+    assert_eq!(aggregate, naive_aggregate);
+}
+
+#[test]
+fn ring_aggregate_matches_naive_sum() {
+    let mut rng = StdRng::seed_from_u64(12);
+    let mut clients: Vec<Client<Wrapping<u32>>> = Client::initialize_multiple(25, 3, &mut rng).unwrap();
+    let naive_aggregate = sum_vectors(clients.iter().map(|client| &client.value), 3);
+    mask_peer_to_peer(&mut clients, &mut rng);
+    assert!(clients.iter().all(|client| client.masked_value != client.value));
+    assert_eq!(ring_aggregate(&clients), naive_aggregate);
+}
+
+#[test]
+fn a_client_can_sum_its_own_group_once_everyone_masked() {
+    let mut server: Server<Wrapping<u32>> = Server::with_seed(10, 6, 2);
+    assert_eq!(server.clients[0].group_aggregate(&server.clients[1..3]), Err(AggregationError::NotInteracted));
+    server.initialize_grouped(3).unwrap();
+    let expected = sum_vectors(server.clients[..3].iter().map(|client| &client.value), 2);
+    assert_eq!(server.clients[0].group_aggregate(&server.clients[1..3]), Ok(expected));
+}
+
+#[test]
+fn participant_count_must_be_positive_and_within_the_limit() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let zero = Client::<Wrapping<u32>>::initialize_multiple(0, 1, &mut rng);
+    assert_eq!(zero.err(), Some(AggregationError::InvalidParticipants { requested: 0, max: MAX_PARTICIPANTS }));
+    let too_many = Client::<Wrapping<u32>>::initialize_multiple(MAX_PARTICIPANTS + 1, 1, &mut rng);
+    assert_eq!(too_many.err(), Some(AggregationError::InvalidParticipants { requested: MAX_PARTICIPANTS + 1, max: MAX_PARTICIPANTS }));
+    assert_eq!(check_participants(MAX_PARTICIPANTS, MAX_PARTICIPANTS), Ok(()));
+    assert_eq!(check_participants(11, 10), Err(AggregationError::InvalidParticipants { requested: 11, max: 10 }));
+    assert_eq!(Client::<Wrapping<u32>>::initialize_multiple(4, 1, &mut rng).unwrap().len(), 4);
+}
+
+#[test]
+fn pairs_lists_every_unordered_pair_once() {
+    for n in 0..20 {
+        let ids: Vec<usize> = (0..n).map(|i| i * 3).collect();
+        let all = pairs(&ids);
+        let unordered: HashSet<(usize, usize)> = all.iter().map(|&(i, j)| (i.min(j), i.max(j))).collect();
+        assert_eq!(all.len(), n * n.saturating_sub(1) / 2);
+        assert_eq!(unordered.len(), all.len());
+        assert!(all.iter().all(|(i, j)| i != j && ids.contains(i) && ids.contains(j)));
+    }
+}
+
+#[test]
+fn grouped_aggregate_matches_full_pairwise() {
+    for (seed, group_size) in [(0, 1), (1, 7), (2, 10), (3, 64)] {
+        let mut full: Server<Wrapping<u32>> = Server::with_seed(seed, 64, 3);
+        let mut grouped: Server<Wrapping<u32>> = Server::with_seed(seed, 64, 3);
+        full.initialize().unwrap();
+        grouped.initialize_grouped(group_size).unwrap();
+        assert_eq!(grouped.aggregate().unwrap(), full.aggregate().unwrap(), "mismatch for seed {} with groups of {}", seed, group_size);
+    }
+}
+
+#[test]
+#[should_panic(expected = "group_size must be at least 1")]
+fn grouping_needs_a_positive_group_size() {
+    let mut server: Server<Wrapping<u32>> = Server::with_seed(7, 5, 1);
+    server.initialize_grouped(0).unwrap();
+}
+
+#[test]
+fn double_masked_aggregate_recovers_with_and_without_dropouts() {
+    for dropout_rate in [0.0, 0.2] {
+        let mut server: Server<Wrapping<u32>> = Server::with_seed(7, 30, 2);
+        server.share_keys(16);
+        server.initialize_double_masked().unwrap();
+        server.simulate_dropout(dropout_rate);
+        let survivors = server.survivors();
+        let naive_aggregate = sum_vectors(survivors.iter().map(|&v| &server.clients[v].value), 2);
+        assert_eq!(server.aggregate_with_recovery().unwrap(), naive_aggregate, "mismatch with dropout rate {}", dropout_rate);
+    }
+}
+
+#[test]
+fn plain_aggregate_removes_the_self_masks_of_a_double_masked_round() {
+    let mut server: Server<Wrapping<u32>> = Server::with_seed(28, 10, 1);
+    server.share_keys(6);
+    server.initialize_double_masked().unwrap();
+    server.simulate_dropout(0.1);
+    let survivors = server.survivors();
+    let naive_aggregate = sum_vectors(survivors.iter().map(|&v| &server.clients[v].value), 1);
+    assert_eq!(server.aggregate(), Ok(naive_aggregate));
+
+    let mut unshared: Server<Wrapping<u32>> = Server::with_seed(28, 10, 1);
+    unshared.initialize_double_masked().unwrap();
+    assert_eq!(unshared.aggregate(), Err(AggregationError::NotEnoughShares { threshold: 0, survivors: 10 }));
+}
+
+#[test]
+fn recovers_a_dropout_from_threshold_survivor_shares() {
+    let mut server: Server<Wrapping<u32>> = Server::with_seed(5, 4, 2);
+    server.share_keys(2);
+    server.initialize().unwrap();
+    server.clients[1].dropped = true;
+    let naive_aggregate = sum_vectors([0, 2, 3].iter().map(|&v| &server.clients[v].value), 2);
+    assert_eq!(server.aggregate_with_recovery().unwrap(), naive_aggregate);
+    server.clients[2].dropped = true;
+    server.clients[3].dropped = true;
+    assert_eq!(server.aggregate_with_recovery(), Err(AggregationError::NotEnoughShares { threshold: 2, survivors: 1 }));
+}
+
+#[test]
+fn rejects_a_submission_of_the_wrong_dimension() {
+    let mut server: Server<Wrapping<u32>> = Server::with_seed(20, 5, 2);
+    server.initialize().unwrap();
+    server.clients[2].masked_value.push(Wrapping(1));
+    assert_eq!(server.aggregate(), Err(AggregationError::DimensionMismatch { expected: 2, got: 3 }));
+}
+
+#[test]
+fn a_dropout_without_key_shares_cannot_be_recovered() {
+    let mut server: Server<Wrapping<u32>> = Server::with_seed(20, 5, 2);
+    server.initialize().unwrap();
+    server.clients[3].dropped = true;
+    assert_eq!(server.aggregate_with_recovery(), Err(AggregationError::MissingClient(3)));
+}
+
+#[test]
+fn dropouts_corrupt_an_aggregate_without_recovery() {
+    let mut server: Server<Wrapping<u32>> = Server::with_seed(26, 50, 1);
+    server.initialize().unwrap();
+    server.simulate_dropout(0.1);
+    let survivors = server.survivors();
+    assert_eq!(survivors.len(), 45);
+    let naive_aggregate = sum_vectors(survivors.iter().map(|&v| &server.clients[v].value), 1);
+    assert_ne!(server.aggregate().unwrap(), naive_aggregate);
+}
+
+#[test]
+fn recovery_repairs_an_aggregate_after_a_10_percent_dropout() {
+    let mut server: Server<Wrapping<u32>> = Server::with_seed(26, 50, 1);
+    server.share_keys(26);
+    server.initialize().unwrap();
+    server.simulate_dropout(0.1);
+    let survivors = server.survivors();
+    assert_eq!(survivors.len(), 45);
+    let naive_aggregate = sum_vectors(survivors.iter().map(|&v| &server.clients[v].value), 1);
+    assert_eq!(server.aggregate_with_recovery().unwrap(), naive_aggregate);
+}
+
+#[test]
+fn aggregate_does_not_depend_on_the_number_of_masking_rounds() {
+    let mut once: Server<Wrapping<u32>> = Server::with_seed(8, 20, 3);
+    let mut five_times: Server<Wrapping<u32>> = Server::with_seed(8, 20, 3);
+    five_times.set_masking_rounds(5);
+    once.initialize().unwrap();
+    five_times.initialize().unwrap();
+    assert_ne!(once.clients[0].masked_value, five_times.clients[0].masked_value);
+    let peer = once.clients[1].public_key;
+    assert_ne!(once.clients[0].pairwise_mask_round(&peer, 1), once.clients[0].pairwise_mask(&peer));
+    assert_eq!(once.aggregate().unwrap(), five_times.aggregate().unwrap());
+
+    let mut recovered: Server<Wrapping<u32>> = Server::with_seed(8, 20, 3);
+    recovered.set_masking_rounds(3);
+    recovered.share_keys(10);
+    recovered.initialize_double_masked().unwrap();
+    recovered.simulate_dropout(0.2);
+    let naive_aggregate = sum_vectors(recovered.survivors().iter().map(|&v| &recovered.clients[v].value), 3);
+    assert_eq!(recovered.aggregate_with_recovery().unwrap(), naive_aggregate);
+}
+
+#[test]
+fn repeated_initialize_does_not_stack_masks() {
+    let mut server: Server<Wrapping<u32>> = Server::with_seed(11, 12, 2);
+    server.share_keys(7);
+    server.initialize_double_masked().unwrap();
+    let masked_once: Vec<Vec<Wrapping<u32>>> = server.clients.iter().map(|client| client.masked_value.clone()).collect();
+    server.initialize_double_masked().unwrap();
+    let masked_twice: Vec<Vec<Wrapping<u32>>> = server.clients.iter().map(|client| client.masked_value.clone()).collect();
+    assert_eq!(masked_once, masked_twice);
+    let naive_aggregate = sum_vectors(server.clients.iter().map(|client| &client.value), 2);
+    assert_eq!(server.aggregate_with_recovery().unwrap(), naive_aggregate);
+}
+
+#[test]
+fn reports_overflow_when_the_true_sum_can_wrap() {
+    let mut narrow: Server<Wrapping<u32>> = Server::with_seed(0, 2, 1);
+    narrow.clip(u32::MAX).unwrap();
+    narrow.initialize().unwrap();
+    assert_eq!(narrow.aggregate(), Err(AggregationError::Overflow));
+
+    let mut wide: Server<Wrapping<u64>> = Server::with_seed(0, 2, 1);
+    wide.clip(u32::MAX).unwrap();
+    wide.initialize().unwrap();
+    assert!(wide.aggregate().is_ok());
+}
+
+#[test]
+fn both_clients_of_a_pair_derive_the_same_mask() {
+    let mut rng = StdRng::seed_from_u64(25);
+    let clients: Vec<Client<Wrapping<u32>>> = Client::initialize_multiple(3, 4, &mut rng).unwrap();
+    let (a, b, c) = (&clients[0], &clients[1], &clients[2]);
+    assert_eq!(a.pairwise_mask(&b.public_key), b.pairwise_mask(&a.public_key));
+    assert_eq!(a.pairwise_mask(&b.public_key).len(), 4);
+    assert_ne!(a.pairwise_mask(&b.public_key), a.pairwise_mask(&c.public_key));
+}
+
+#[test]
+fn prg_masks_are_deterministic_and_cancel() {
+    let seed = [7; 32];
+    let mask: Vec<Wrapping<u32>> = mask::prg_mask(seed, 16);
+    assert_eq!(mask, mask::prg_mask::<Wrapping<u32>>(seed, 16));
+    assert_ne!(mask, mask::prg_mask::<Wrapping<u32>>([8; 32], 16));
+    // A shorter mask is a prefix of a longer one from the same seed.
+    assert_eq!(mask[..4], mask::prg_mask::<Wrapping<u32>>(seed, 4)[..]);
+
+    let value = vec![Wrapping(9u32); 16];
+    let masked: Vec<Wrapping<u32>> = value.iter().zip(&mask).map(|(x, m)| x + m).collect();
+    assert_ne!(masked, value);
+    let unmasked: Vec<Wrapping<u32>> = masked.iter().zip(&mask).map(|(x, m)| x - m).collect();
+    assert_eq!(unmasked, value);
+}
+
+#[test]
+fn net_masks_of_all_clients_cancel() {
+    let mut full: Server<Wrapping<u32>> = Server::with_seed(4, 25, 3);
+    full.initialize().unwrap();
+    assert_eq!(full.net_mask(), vec![Wrapping(0); 3]);
+    assert!(full.clients.iter().any(|client| client.net_mask != vec![Wrapping(0); 3]));
+
+    let mut grouped: Server<Wrapping<u32>> = Server::with_seed(4, 25, 3);
+    grouped.initialize_grouped(4).unwrap();
+    assert_eq!(grouped.net_mask(), vec![Wrapping(0); 3]);
+}
+
+#[test]
+fn verify_masks_reports_any_residual() {
+    let mut server: Server<Wrapping<u32>> = Server::with_seed(6, 15, 2);
+    server.share_keys(8);
+    server.initialize_double_masked().unwrap();
+    assert!(server.verify_masks());
+    assert_eq!(server.mask_residual(), vec![Wrapping(0); 2]);
+
+    server.clients[4].net_mask[1] += Wrapping(3);
+    assert!(!server.verify_masks());
+    assert_eq!(server.mask_residual(), vec![Wrapping(0), Wrapping(3)]);
+}
+
+#[test]
+fn clip_bounds_the_l2_norm_of_a_value() {
+    let mut rng = StdRng::seed_from_u64(19);
+    let mut scalar = Client::new(vec![Wrapping(100i64)], &mut rng);
+    scalar.clip(10).unwrap();
+    assert_eq!(scalar.value, vec![Wrapping(10)]);
+    let mut negative = Client::new(vec![Wrapping(-100i64)], &mut rng);
+    negative.clip(10).unwrap();
+    assert_eq!(negative.value, vec![Wrapping(-10)]);
+    let mut small = Client::new(vec![Wrapping(4i64)], &mut rng);
+    small.clip(10).unwrap();
+    assert_eq!(small.value, vec![Wrapping(4)]);
+
+    let mut vector = Client::new(vec![Wrapping(30i64), Wrapping(40)], &mut rng);
+    vector.clip(10).unwrap();
+    assert_eq!(vector.value, vec![Wrapping(6), Wrapping(8)]);
+    assert_eq!(vector.masked_value, vector.value);
+
+    let mut server: Server<Wrapping<u32>> = Server::with_seed(19, 10, 3);
+    server.clip(5).unwrap();
+    for client in &server.clients {
+        assert!(client.value.iter().map(|x| (x.0 as f64).powi(2)).sum::<f64>().sqrt() <= 5.0);
+    }
+    let naive_aggregate = sum_vectors(server.clients.iter().map(|client| &client.value), 3);
+    server.initialize().unwrap();
+    assert_eq!(server.aggregate(), Ok(naive_aggregate));
+}
+
+#[test]
+fn clipping_is_refused_once_the_clients_have_masked() {
+    let mut server: Server<Wrapping<u32>> = Server::with_seed(19, 10, 3);
+    server.initialize().unwrap();
+    let masked: Vec<Vec<Wrapping<u32>>> = server.clients.iter().map(|client| client.masked_value.clone()).collect();
+    assert_eq!(server.clip(5), Err(AggregationError::WrongPhase(Phase::Initialized)));
+    assert_eq!(server.clients[0].clip(5), Err(AggregationError::WrongPhase(Phase::Initialized)));
+    assert_eq!(server.clients.iter().map(|client| client.masked_value.clone()).collect::<Vec<_>>(), masked);
+}
+
+#[test]
+fn weighted_aggregate_sums_weighted_values() {
+    let mut server: Server<Wrapping<u32>> = Server::with_seed(5, 2, 1);
+    server.clients[0].value = vec![Wrapping(6)];
+    server.clients[1].value = vec![Wrapping(10)];
+    server.set_weights(&[3, 1]).unwrap();
+    server.initialize().unwrap();
+    assert_eq!(server.aggregate(), Ok(vec![Wrapping(28)]));
+    assert_eq!(server.total_weight(), 4);
+}
+
+#[test]
+#[should_panic(expected = "need one weight per client")]
+fn set_weights_needs_a_weight_for_every_client() {
+    let mut server: Server<Wrapping<u32>> = Server::with_seed(5, 3, 1);
+    server.set_weights(&[3, 1]).unwrap();
+}
+
+#[test]
+fn weights_cannot_change_after_masking() {
+    let mut server: Server<Wrapping<u32>> = Server::with_seed(5, 3, 1);
+    server.initialize().unwrap();
+    let masked: Vec<Vec<Wrapping<u32>>> = server.clients.iter().map(|client| client.masked_value.clone()).collect();
+    assert_eq!(server.set_weights(&[3, 1, 2]), Err(AggregationError::WrongPhase(Phase::Initialized)));
+    assert_eq!(server.clients[0].set_weight(3), Err(AggregationError::WrongPhase(Phase::Initialized)));
+    assert_eq!(server.clients.iter().map(|client| client.masked_value.clone()).collect::<Vec<_>>(), masked);
+    assert!(server.clients.iter().all(|client| client.weight == 1));
+}
+
+#[test]
+fn late_client_joins_an_initialized_round() {
+    let mut server: Server<Wrapping<u32>> = Server::with_seed(6, 3, 2);
+    server.initialize().unwrap();
+    let late = server.join(vec![Wrapping(7), Wrapping(9)]).unwrap();
+    assert_eq!(late, 3);
+    let expected = sum_vectors(server.clients.iter().map(|client| &client.value), 2);
+    assert_eq!(server.aggregate(), Ok(expected));
+    assert_eq!(server.net_mask(), vec![Wrapping(0); 2]);
+    assert_eq!(server.interaction_order()[3..], [(0, 3), (1, 3), (2, 3)]);
+}
+
+#[test]
+fn late_client_adds_a_self_mask_in_a_double_masked_round() {
+    let mut server: Server<Wrapping<u32>> = Server::with_seed(6, 6, 1);
+    server.initialize_double_masked().unwrap();
+    let late = server.join(vec![Wrapping(7)]).unwrap();
+    assert_ne!(server.clients[late].masked_value, vec![Wrapping(7)]);
+    server.share_keys(4);
+    let expected = sum_vectors(server.clients.iter().map(|client| &client.value), 1);
+    assert_eq!(server.aggregate_with_recovery(), Ok(expected));
+    assert!(server.verify_masks());
+}
+
+#[test]
+fn late_clients_cannot_join_grouped_or_shared_rounds() {
+    let mut grouped: Server<Wrapping<u32>> = Server::with_seed(6, 6, 1);
+    grouped.initialize_grouped(3).unwrap();
+    assert_eq!(grouped.join(vec![Wrapping(7)]), Err(AggregationError::CannotJoin));
+
+    let mut shared: Server<Wrapping<u32>> = Server::with_seed(6, 6, 1);
+    shared.share_keys(4);
+    shared.initialize().unwrap();
+    assert_eq!(shared.join(vec![Wrapping(7)]), Err(AggregationError::CannotJoin));
+    assert_eq!(shared.clients.len(), 6);
+}
+
+struct ConstantMask;
+
+impl MaskingStrategy<Wrapping<u32>> for ConstantMask {
+    fn pairwise_mask(&self, a: &Client<Wrapping<u32>>, _b: &Client<Wrapping<u32>>, _rng: &mut dyn RngCore) -> Vec<Wrapping<u32>> {
+        vec![Wrapping(u32::MAX); a.value.len()]
+    }
+}
+
+#[test]
+fn custom_masking_strategies_still_cancel() {
+    let strategies: [&dyn MaskingStrategy<Wrapping<u32>>; 2] = [&ConstantMask, &RandomPairwise];
+    for strategy in strategies {
+        let mut server: Server<Wrapping<u32>> = Server::with_seed(8, 20, 2);
+        server.initialize_with(strategy).unwrap();
+        let expected = sum_vectors(server.clients.iter().map(|client| &client.value), 2);
+        assert_eq!(server.aggregate(), Ok(expected));
+    }
+}
+
+#[test]
+fn dropouts_under_unrecoverable_strategies_are_refused() {
+    let strategies: [&dyn MaskingStrategy<Wrapping<u32>>; 3] = [&ConstantMask, &RandomPairwise, &BoundedPairwise { bound: 100 }];
+    for strategy in strategies {
+        let mut server: Server<Wrapping<u32>> = Server::with_seed(8, 10, 1);
+        server.share_keys(6);
+        server.initialize_with(strategy).unwrap();
+        server.clients[4].dropped = true;
+        assert_eq!(server.aggregate_with_recovery(), Err(AggregationError::MissingClient(4)));
+    }
+
+    // Without dropouts there is nothing to rebuild, so the masks still cancel.
+    let mut server: Server<Wrapping<u32>> = Server::with_seed(8, 10, 1);
+    server.share_keys(6);
+    server.initialize_with(&RandomPairwise).unwrap();
+    let expected = sum_vectors(server.clients.iter().map(|client| &client.value), 1);
+    assert_eq!(server.aggregate_with_recovery(), Ok(expected));
+}
+
+#[test]
+fn bounded_masks_stay_small_and_still_cancel() {
+    let mut server: Server<Wrapping<u32>> = Server::with_seed(8, 2, 50);
+    server.initialize_with(&BoundedPairwise { bound: 100 }).unwrap();
+    let client = &server.clients[0];
+    assert!(client.net_mask.iter().all(|m| m.0 < 100));
+    assert_eq!(client.masked_value.iter().zip(&client.value).map(|(m, v)| m - v).collect::<Vec<_>>(), client.net_mask);
+    let expected = sum_vectors(server.clients.iter().map(|client| &client.value), 50);
+    assert_eq!(server.aggregate(), Ok(expected));
+}
+
+#[test]
+#[should_panic(expected = "BoundedPairwise needs a bound of at least 1")]
+fn bounded_masks_need_a_positive_bound() {
+    let mut server: Server<Wrapping<u32>> = Server::with_seed(8, 2, 1);
+    server.initialize_with(&BoundedPairwise { bound: 0 }).unwrap();
+}
+
+#[test]
+fn flags_an_aggregate_no_honest_clients_could_produce() {
+    let mut server: Server<Wrapping<u32>> = Server::with_seed(9, 10, 1);
+    server.initialize().unwrap();
+    server.aggregate().unwrap();
+    assert!(!server.is_anomalous());
+
+    let mut server: Server<Wrapping<u32>> = Server::with_seed(9, 10, 1);
+    server.clients[3].value = vec![Wrapping(1_000_000)];
+    server.clients[3].masked_value = vec![Wrapping(1_000_000)];
+    server.initialize().unwrap();
+    server.aggregate().unwrap();
+    assert!(server.is_anomalous());
+    server.set_max_reasonable_aggregate(2_000_000);
+    assert!(!server.is_anomalous());
+}
+
+#[test]
+fn clients_can_verify_their_contribution_was_committed() {
+    let mut server: Server<Wrapping<u32>> = Server::with_seed(10, 7, 2);
+    server.initialize().unwrap();
+    server.clients[4].dropped = true;
+    server.aggregate().unwrap();
+    let root = server.commitment_root();
+    for id in [0, 3, 6] {
+        let proof = server.inclusion_proof(id).unwrap();
+        assert!(server.clients[id].verify_inclusion(id, &root, &proof));
+        assert!(!server.clients[id].verify_inclusion(id + 1, &root, &proof));
+    }
+    assert!(server.inclusion_proof(4).is_none());
+}
+
+#[test]
+fn signed_results_verify_only_untampered() {
+    let mut server: Server<Wrapping<u32>> = Server::with_seed(12, 5, 2);
+    server.initialize().unwrap();
+    let mut aggregate = server.aggregate().unwrap();
+    let signature = server.sign_result();
+    assert!(verify_result(&server.verifying_key(), &aggregate, &signature));
+    aggregate[1] += 1;
+    assert!(!verify_result(&server.verifying_key(), &aggregate, &signature));
+}
+
+#[test]
+fn signed_values_aggregate_to_a_signed_total() {
+    let mut server: Server<Wrapping<i64>> = Server::with_seed(13, 4, 1);
+    for (client, value) in server.clients.iter_mut().zip([-40, 25, -7, 3]) {
+        client.value = vec![Wrapping(value)];
+        client.masked_value = vec![Wrapping(value)];
+    }
+    server.initialize().unwrap();
+    assert_eq!(server.aggregate(), Ok(vec![Wrapping(-19)]));
+
+    let mut server: Server<Wrapping<i64>> = Server::with_seed(13, 2, 1);
+    for (client, update) in server.clients.iter_mut().zip([-0.75, 0.25]) {
+        client.value = vec![quant::quantize_i64(update, 1000.0)];
+        client.masked_value = client.value.clone();
+    }
+    server.initialize().unwrap();
+    assert_eq!(server.aggregate_dequantized(1000.0), Ok(vec![-0.25]));
+}
+
+#[test]
+fn mean_divides_by_the_contributing_weight() {
+    let mut server: Server<Wrapping<u32>> = Server::with_seed(14, 3, 1);
+    for (client, value) in server.clients.iter_mut().zip([4, 6, 11]) {
+        client.value = vec![Wrapping(value)];
+        client.masked_value = vec![Wrapping(value)];
+    }
+    server.set_weights(&[1, 1, 2]).unwrap();
+    server.initialize().unwrap();
+    assert_eq!(server.aggregate_mean(), Ok(vec![8.0]));
+
+    let mut empty: Server<Wrapping<u32>> = Server::with_seed(14, 1, 1);
+    empty.clients[0].dropped = true;
+    assert_eq!(empty.aggregate_mean(), Err(AggregationError::NoContributors));
+}
+
+#[test]
+fn shuffled_peer_order_is_seeded_and_keeps_the_aggregate() {
+    let run = |shuffle: bool| {
+        let mut server: Server<Wrapping<u32>> = Server::with_seed(15, 12, 2);
+        if shuffle {
+            server.shuffle_peers();
+        }
+        server.initialize_grouped(4).unwrap();
+        let expected = sum_vectors(server.clients.iter().map(|client| &client.value), 2);
+        assert_eq!(server.aggregate(), Ok(expected));
+        server.interaction_order().to_vec()
+    };
+    let ordered = run(false);
+    let shuffled = run(true);
+    let mut sorted = shuffled.clone();
+    sorted.sort_unstable();
+    assert_eq!(sorted, ordered);
+    assert_ne!(shuffled, ordered);
+    assert_eq!(run(true), shuffled);
+}
+
+#[test]
+fn clients_refuse_to_mask_with_peers_they_have_not_handshaken() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let mut clients: Vec<Client<Wrapping<u32>>> = Client::initialize_multiple(3, 2, &mut rng).unwrap();
+    let (first, others) = clients.split_first_mut().unwrap();
+    assert_eq!(first.interact_with_others(others, &RandomPairwise, &mut rng), Err(AggregationError::NoHandshake));
+    assert_eq!(first.masked_value, first.value);
+
+    let forged = Client::<Wrapping<u32>>::new(vec![Wrapping(0); 2], &mut rng);
+    assert!(!first.handshake(forged.public_key, Some(&others[0].fingerprint())));
+    for other in others.iter_mut() {
+        assert!(first.handshake(other.public_key, Some(&other.fingerprint())));
+        other.handshake(first.public_key, None);
+    }
+    assert!(first.interact_with_others(others, &RandomPairwise, &mut rng).is_ok());
+    assert_ne!(first.masked_value, first.value);
+}
+
+#[test]
+fn refuses_to_initialize_clients_with_duplicate_identities() {
+    let mut server: Server<Wrapping<u32>> = Server::with_seed(0, 1, 1);
+    for _ in 0..2 {
+        server.clients.push(Client::new(vec![Wrapping(7)], &mut StdRng::seed_from_u64(4)));
+    }
+    assert_eq!(server.initialize(), Err(AggregationError::DuplicateClient(2)));
+    assert_eq!(server.phase(), Phase::Idle);
+}
+
+#[test]
+fn survivors_abort_when_told_different_survivor_sets() {
+    let mut server: Server<Wrapping<u32>> = Server::with_seed(18, 10, 1);
+    server.share_keys(6);
+    server.clients[9].dropped = true;
+    let survivors = server.survivors();
+    assert_eq!(server.consistency_check_with(&vec![survivors.clone(); 9]), Ok(()));
+
+    // Five survivors are told client 9 dropped and four that it did not, so no set gathers six signatures.
+    let mut reported = vec![survivors; 5];
+    reported.extend(vec![(0..10).collect::<Vec<usize>>(); 4]);
+    assert_eq!(server.consistency_check_with(&reported), Err(AggregationError::InconsistentSurvivors(0)));
+}
+
+#[test]
+#[should_panic(expected = "need one reported survivor set per survivor")]
+fn consistency_check_needs_a_set_for_every_survivor() {
+    let server: Server<Wrapping<u32>> = Server::with_seed(18, 10, 1);
+    server.consistency_check_with(&vec![server.survivors(); 9]).unwrap();
+}
+
+#[test]
+fn saved_and_loaded_server_produces_the_same_aggregate() {
+    let mut server: Server<Wrapping<u32>> = Server::with_seed(9, 12, 2);
+    server.share_keys(7);
+    server.initialize_double_masked().unwrap();
+    server.simulate_dropout(0.25);
+    let path = std::env::temp_dir().join(format!("secagg-server-{}.json", std::process::id()));
+    server.save(&path).unwrap();
+    let mut loaded: Server<Wrapping<u32>> = Server::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded.survivors(), server.survivors());
+    assert_eq!(loaded.aggregate_with_recovery().unwrap(), server.aggregate_with_recovery().unwrap());
+    assert_eq!(loaded.verifying_key(), server.verifying_key());
+}
+
+#[test]
+fn named_streams_aggregate_separately() {
+    let mut server: Server<Wrapping<u32>> = Server::with_seed(15, 10, 2);
+    server.name_streams(&["sum", "count"]);
+    for client in 0..10 {
+        let values = HashMap::from([("sum".to_string(), Wrapping(client as u32)), ("count".to_string(), Wrapping(1))]);
+        server.set_stream_values(client, &values).unwrap();
+    }
+    server.initialize().unwrap();
+    assert_ne!(server.clients[0].net_mask[0], server.clients[0].net_mask[1]);
+    let masked = server.clients[3].masked_value.clone();
+    let late = HashMap::from([("sum".to_string(), Wrapping(100))]);
+    assert_eq!(server.set_stream_values(3, &late), Err(AggregationError::WrongPhase(Phase::Initialized)));
+    assert_eq!(server.clients[3].masked_value, masked);
+    let expected = HashMap::from([("sum".to_string(), Wrapping(45)), ("count".to_string(), Wrapping(10))]);
+    assert_eq!(server.aggregate_streams(), Ok(expected));
+}
+
+#[test]
+fn rounds_move_through_their_phases_in_order() {
+    let mut server: Server<Wrapping<u32>> = Server::with_seed(16, 5, 1);
+    assert_eq!(server.phase(), Phase::Idle);
+    assert_eq!(server.aggregate(), Err(AggregationError::NotInitialized));
+    assert_eq!(server.aggregate_with_recovery(), Err(AggregationError::NotInitialized));
+
+    server.initialize().unwrap();
+    assert_eq!(server.phase(), Phase::Initialized);
+    let aggregate = server.aggregate().unwrap();
+    assert_eq!(server.phase(), Phase::Aggregated);
+
+    let masked: Vec<Vec<Wrapping<u32>>> = server.clients.iter().map(|client| client.masked_value.clone()).collect();
+    assert_eq!(server.initialize(), Err(AggregationError::WrongPhase(Phase::Aggregated)));
+    assert_eq!(server.initialize_double_masked(), Err(AggregationError::WrongPhase(Phase::Aggregated)));
+    assert_eq!(server.join(vec![Wrapping(7)]), Err(AggregationError::WrongPhase(Phase::Aggregated)));
+    assert_eq!(server.phase(), Phase::Aggregated);
+    assert_eq!(server.clients.len(), 5);
+    assert_eq!(server.clients.iter().map(|client| client.masked_value.clone()).collect::<Vec<_>>(), masked);
+    assert_eq!(server.aggregate(), Ok(aggregate));
+
+    let mut idle: Server<Wrapping<u32>> = Server::with_seed(16, 5, 1);
+    assert_eq!(idle.join(vec![Wrapping(7)]), Err(AggregationError::WrongPhase(Phase::Idle)));
+    assert_eq!(idle.clients.len(), 5);
+}
+
+#[test]
+fn detects_a_true_sum_that_wraps_the_modulus() {
+    fn with_large_values<T: MaskableValue>(value: T) -> Server<T> {
+        let mut server: Server<T> = Server::with_seed(17, 3, 1);
+        for client in server.clients.iter_mut() {
+            client.value = vec![value];
+            client.masked_value = vec![value];
+        }
+        server.initialize().unwrap();
+        server.aggregate().unwrap();
+        server
+    }
+    let narrow = with_large_values(Wrapping(2_000_000_000u32));
+    assert_eq!(narrow.reference_sum(), Some(vec![6_000_000_000]));
+    assert!(narrow.aggregate_wrapped());
+
+    let wide = with_large_values(Wrapping(3_000_000_000u64));
+    assert_eq!(wide.reference_sum(), Some(vec![9_000_000_000]));
+    assert!(!wide.aggregate_wrapped());
+}
+
+#[test]
+fn negative_values_and_dropouts_are_not_reported_as_wraps() {
+    let mut noisy: Server<Wrapping<u32>> = Server::with_seed(1, 50, 1);
+    noisy.add_dp_noise(Noise::Gaussian { sigma: 20.0 });
+    noisy.initialize().unwrap();
+    noisy.aggregate().unwrap();
+    assert_eq!(noisy.reference_sum(), None);
+    assert!(!noisy.aggregate_wrapped());
+
+    let mut quantized: Server<Wrapping<u32>> = Server::with_seed(1, 2, 1);
+    for (client, x) in quantized.clients.iter_mut().zip([-0.5, 0.25]) {
+        client.value = vec![quant::quantize(x, 1000.0, 32)];
+        client.masked_value = client.value.clone();
+    }
+    quantized.initialize().unwrap();
+    assert_eq!(quantized.aggregate_dequantized(1000.0), Ok(vec![-0.125]));
+    assert!(!quantized.aggregate_wrapped());
+
+    let mut dropped: Server<Wrapping<u32>> = Server::with_seed(1, 10, 1);
+    dropped.initialize().unwrap();
+    dropped.clients[3].dropped = true;
+    dropped.aggregate().unwrap();
+    assert!(!dropped.aggregate_wrapped());
+}
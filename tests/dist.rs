@@ -0,0 +1,50 @@
+use rand::{rngs::StdRng, SeedableRng};
+use secure_aggregation_tutorial::{sum_vectors, Client, Server, ValueDist};
+use std::num::Wrapping;
+
+fn sample(dist: ValueDist) -> Vec<u32> {
+    let mut rng = StdRng::seed_from_u64(0);
+    let clients: Vec<Client<Wrapping<u32>>> = Client::initialize_multiple_with(dist, 200, 3, &mut rng).unwrap();
+    clients.iter().flat_map(|client| client.value.iter().map(|x| x.0)).collect()
+}
+
+#[test]
+fn uniform_values_cover_the_whole_range() {
+    let values = sample(ValueDist::Uniform { low: 2, high: 4 });
+    assert!(values.iter().all(|x| (2..=4).contains(x)));
+    assert!((2..=4).all(|x| values.contains(&x)));
+}
+
+#[test]
+fn constant_values_never_vary() {
+    assert!(sample(ValueDist::Constant(9)).iter().all(|&x| x == 9));
+}
+
+#[test]
+fn zipf_values_favour_low_ranks() {
+    let values = sample(ValueDist::Zipf { n: 10, s: 1.5 });
+    assert!(values.iter().all(|x| (1..=10).contains(x)));
+    let count = |k: u32| values.iter().filter(|&&x| x == k).count();
+    assert!(count(1) > count(2) && count(2) > count(10));
+}
+
+#[test]
+fn normal_values_are_clamped_at_zero() {
+    let values = sample(ValueDist::Normal { mean: 1.0, std_dev: 5.0 });
+    assert!(values.contains(&0));
+    assert!(values.iter().all(|&x| x < 100));
+    let mean = values.iter().map(|&x| x as f64).sum::<f64>() / values.len() as f64;
+    assert!(mean > 1.0, "clamping negative draws to zero raises the mean, got {}", mean);
+}
+
+#[test]
+fn normal_values_stay_within_a_finite_bound() {
+    let dist = ValueDist::Normal { mean: 8.0, std_dev: 1.0 };
+    assert_eq!(dist.max(), 14);
+    assert!(sample(ValueDist::Normal { mean: 8.0, std_dev: 40.0 }).iter().all(|&x| x <= 248));
+
+    let mut server: Server<Wrapping<u32>> = Server::with_dist(StdRng::seed_from_u64(0), dist, 10, 1);
+    let naive_aggregate = sum_vectors(server.clients.iter().map(|client| &client.value), 1);
+    server.initialize().unwrap();
+    assert_eq!(server.aggregate(), Ok(naive_aggregate));
+}
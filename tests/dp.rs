@@ -0,0 +1,47 @@
+use rand::{rngs::StdRng, SeedableRng};
+use secure_aggregation_tutorial::dp::{self, Noise};
+use std::num::Wrapping;
+
+fn sample_std_dev(noise: Noise) -> f64 {
+    let mut rng = StdRng::seed_from_u64(0);
+    let samples: Vec<f64> = (0..20_000)
+        .map(|_| {
+            let mut value = Wrapping(0i64);
+            dp::add_noise(&mut value, noise, &mut rng);
+            value.0 as f64
+        })
+        .collect();
+    let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+    (samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / samples.len() as f64).sqrt()
+}
+
+#[test]
+fn gaussian_noise_has_standard_deviation_sigma() {
+    let std_dev = sample_std_dev(Noise::Gaussian { sigma: 20.0 });
+    assert!((std_dev - 20.0).abs() < 0.5, "sample standard deviation {}", std_dev);
+}
+
+#[test]
+fn laplace_noise_has_standard_deviation_sqrt_2_times_scale() {
+    let std_dev = sample_std_dev(Noise::Laplace { scale: 20.0 });
+    assert!((std_dev - 20.0 * 2f64.sqrt()).abs() < 1.0, "sample standard deviation {}", std_dev);
+}
+
+#[test]
+fn negative_noise_wraps_around_the_modulus() {
+    let mut unsigned_rng = StdRng::seed_from_u64(1);
+    let mut signed_rng = StdRng::seed_from_u64(1);
+    let mut negatives = 0;
+    for _ in 0..1000 {
+        let mut unsigned = Wrapping(3u32);
+        let mut signed = Wrapping(3i64);
+        dp::add_gaussian_noise(&mut unsigned, 10.0, &mut unsigned_rng);
+        dp::add_gaussian_noise(&mut signed, 10.0, &mut signed_rng);
+        assert_eq!(unsigned.0 as i32 as i64, signed.0);
+        if signed.0 < 0 {
+            negatives += 1;
+            assert!(unsigned.0 > u32::MAX - 100);
+        }
+    }
+    assert!(negatives > 0);
+}
@@ -0,0 +1,142 @@
+use clap::error::ErrorKind;
+use clap::Parser;
+use secure_aggregation_tutorial::experiment::{self, Config, Mode, Outcome, Report, RoundRecord, Verbosity};
+use secure_aggregation_tutorial::AggregationError;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::num::Wrapping;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing_subscriber::EnvFilter;
+
+#[test]
+fn parses_arguments() {
+    let config = Config::try_parse_from(["secagg", "--mode", "double-mask", "--participants", "20", "--seed", "3", "--dim", "4"]).unwrap();
+    assert_eq!(config.mode, Mode::DoubleMask);
+    assert_eq!((config.participants, config.seed, config.dim), (20, Some(3), 4));
+
+    let defaults = Config::try_parse_from(["secagg"]).unwrap();
+    assert_eq!((defaults.mode, defaults.participants, defaults.seed, defaults.dim), (Mode::Pairwise, 100, None, 1));
+
+    assert!(Config::try_parse_from(["secagg", "--mode", "shuffle"]).is_err());
+    assert!(Config::try_parse_from(["secagg", "--participants", "many"]).is_err());
+    assert!(Config::try_parse_from(["secagg", "--participants", "-3"]).is_err());
+
+    let capped = Config::try_parse_from(["secagg", "--participants", "20", "--max-participants", "10"]).unwrap();
+    assert!(experiment::run(&capped).is_err());
+}
+
+#[test]
+fn reports_missing_and_invalid_arguments() {
+    let kind = |args: &[&str]| Config::try_parse_from(args).unwrap_err().kind();
+    assert_eq!(kind(&["secagg", "--participants"]), ErrorKind::InvalidValue);
+    assert_eq!(kind(&["secagg", "--seed"]), ErrorKind::InvalidValue);
+    assert_eq!(kind(&["secagg", "--participants", "many"]), ErrorKind::ValueValidation);
+    assert_eq!(kind(&["secagg", "--dim", "1.5"]), ErrorKind::ValueValidation);
+    assert_eq!(kind(&["secagg", "--masking-rounds", "0"]), ErrorKind::ValueValidation);
+    assert_eq!(kind(&["secagg", "--mode", "shuffle"]), ErrorKind::InvalidValue);
+    assert_eq!(kind(&["secagg", "20"]), ErrorKind::UnknownArgument);
+    assert_eq!(kind(&["secagg", "--clients", "20"]), ErrorKind::UnknownArgument);
+    // Usage errors exit with code 2, as main does for errors from config::load.
+    assert_eq!(Config::try_parse_from(["secagg", "--seed"]).unwrap_err().exit_code(), 2);
+}
+
+#[test]
+fn rejects_inputs_the_modes_cannot_handle() {
+    let config = Config::try_parse_from(["secagg", "--mode", "double-mask", "--participants", "300", "--seed", "1"]).unwrap();
+    assert_eq!(experiment::run(&config).err(), Some(AggregationError::InvalidParticipants { requested: 300, max: 255 }));
+    assert!(experiment::run(&Config { mode: Mode::Pairwise, participants: 30, ..config.clone() }).is_ok());
+
+    let raised = Config { mode: Mode::Pairwise, participants: 1_000_001, max_participants: 2_000_000, ..config.clone() };
+    assert_eq!(experiment::run(&raised).err(), Some(AggregationError::InvalidParticipants { requested: 2_000_000, max: 1_000_000 }));
+
+    let unmasked = Config { mode: Mode::Pairwise, participants: 20, masking_rounds: 0, ..config.clone() };
+    assert_eq!(experiment::run(&unmasked).err(), Some(AggregationError::InvalidMaskingRounds));
+
+    for dropout_rate in [2.0, -0.1, f64::NAN] {
+        let config = Config { participants: 20, dropout_rate, ..config.clone() };
+        assert_eq!(experiment::run(&config).err(), Some(AggregationError::InvalidDropoutRate));
+    }
+}
+
+#[test]
+fn every_mode_produces_a_matching_aggregate() {
+    for mode in [Mode::Naive, Mode::Pairwise, Mode::DoubleMask, Mode::Dp, Mode::Ring] {
+        let config = Config::try_parse_from(["secagg", "--participants", "20", "--seed", "5", "--dim", "3"]).unwrap();
+        let outcome = experiment::run(&Config { mode, ..config }).unwrap();
+        assert!(outcome.matches(), "{:?} mode did not match the naive aggregate", mode);
+    }
+}
+
+#[test]
+fn json_report_has_every_field() {
+    let config = Config::try_parse_from(["secagg", "--mode", "dp", "--participants", "12", "--seed", "9", "--dim", "2", "--output", "json"]).unwrap();
+    let outcome = experiment::run(&config).unwrap();
+    let json = serde_json::to_string(&Report::new(&config, &outcome)).unwrap();
+    let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(value["mode"], "dp");
+    assert_eq!(value["participants"], 12);
+    assert_eq!(value["naive_aggregate"], value["secure_aggregate"]);
+    assert_eq!(value["secure_aggregate"].as_array().unwrap().len(), 2);
+    assert_eq!(value["match"], true);
+}
+
+#[test]
+fn exit_code_reports_mismatches() {
+    let config = Config::try_parse_from(["secagg", "--participants", "10", "--seed", "1"]).unwrap();
+    assert_eq!(experiment::run(&config).unwrap().exit_code(), 0);
+
+    let mismatch = Outcome { naive_aggregate: vec![Wrapping(80)], secure_aggregate: vec![Wrapping(81)], dropouts: 0 };
+    assert!(!mismatch.matches());
+    assert_eq!(mismatch.exit_code(), 1);
+}
+
+#[test]
+fn csv_export_appends_one_row_per_round() {
+    let path = env::temp_dir().join(format!("secagg-rounds-{}.csv", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let config = Config::try_parse_from(["secagg", "--mode", "double-mask", "--participants", "10", "--seed", "2", "--dim", "2", "--dropout-rate", "0.2"]).unwrap();
+    let outcome = experiment::run(&config).unwrap();
+    assert_eq!(experiment::append_csv(&path, &config, &outcome, Duration::from_millis(5)).unwrap(), 1);
+    assert_eq!(experiment::append_csv(&path, &config, &outcome, Duration::from_millis(7)).unwrap(), 2);
+
+    let records: Vec<RoundRecord> = csv::Reader::from_path(&path).unwrap().deserialize().map(Result::unwrap).collect();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(records.iter().map(|record| record.round).collect::<Vec<u64>>(), [1, 2]);
+    assert_eq!((records[1].mode, records[1].participants, records[1].dropouts), (Mode::DoubleMask, 10, 2));
+    assert_eq!(records[1].aggregate.split(' ').count(), 2);
+    assert_eq!(records[1].wall_clock_ms, 7.0);
+    assert!(records.iter().all(|record| record.matches));
+}
+
+#[derive(Clone, Default)]
+struct Capture(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Capture {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn log_lines(verbosity: Verbosity) -> usize {
+    let capture = Capture::default();
+    let writer = capture.clone();
+    let subscriber = tracing_subscriber::fmt().with_env_filter(EnvFilter::new(verbosity.filter())).with_writer(move || writer.clone()).finish();
+    let config = Config::try_parse_from(["secagg", "--participants", "6", "--seed", "4"]).unwrap();
+    tracing::subscriber::with_default(subscriber, || experiment::log_outcome(&experiment::run(&config).unwrap()));
+    let output = capture.0.lock().unwrap().clone();
+    String::from_utf8(output).unwrap().lines().count()
+}
+
+#[test]
+fn verbosity_controls_how_much_is_logged() {
+    assert_eq!(log_lines(Verbosity::Quiet), 1);
+    assert_eq!(log_lines(Verbosity::Normal), 2);
+    // Six clients form fifteen pairs, each logged as it interacts.
+    assert_eq!(log_lines(Verbosity::Verbose), 17);
+}
@@ -0,0 +1,40 @@
+use rand::{rngs::StdRng, SeedableRng};
+use secure_aggregation_tutorial::field::{Mersenne31, PrimeField};
+use secure_aggregation_tutorial::Server;
+
+#[test]
+fn subtraction_wraps_below_zero() {
+    assert_eq!(PrimeField::<7>(0) - PrimeField(1), PrimeField(6));
+    assert_eq!(PrimeField::<7>(2) - PrimeField(6), PrimeField(3));
+    assert_eq!(PrimeField::<2_147_483_647>(0) - PrimeField(2_147_483_646), PrimeField(1));
+    for a in 0..7 {
+        for b in 0..7 {
+            assert_eq!(PrimeField::<7>(a) - PrimeField(b) + PrimeField(b), PrimeField(a));
+        }
+    }
+}
+
+// Chi-squared test with 4 degrees of freedom. 5 is a bad case for a masked u64 mod P,
+// as three of the eight 3-bit patterns land out of range; 18.47 is the 0.1% critical value.
+#[test]
+fn sampled_masks_are_uniform() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let draws = 50_000;
+    let mut counts = [0u32; 5];
+    for _ in 0..draws {
+        counts[PrimeField::<5>::sample_uniform(&mut rng).0 as usize] += 1;
+    }
+    let expected = draws as f64 / 5.0;
+    let chi_squared: f64 = counts.iter().map(|&c| (c as f64 - expected).powi(2) / expected).sum();
+    assert!(chi_squared < 18.47, "counts {:?} give chi-squared {}", counts, chi_squared);
+}
+
+#[test]
+fn server_over_mersenne31_aggregates_to_the_sum_mod_p() {
+    let mut server: Server<Mersenne31> = Server::with_seed(21, 30, 4);
+    let naive_aggregate: Vec<Mersenne31> = (0..4)
+        .map(|k| PrimeField(server.clients.iter().map(|client| client.value[k].0).sum::<u64>() % 2_147_483_647))
+        .collect();
+    server.initialize().unwrap();
+    assert_eq!(server.aggregate(), Ok(naive_aggregate));
+}
@@ -0,0 +1,20 @@
+use proptest::prelude::*;
+use secure_aggregation_tutorial::Server;
+use std::num::Wrapping;
+
+proptest! {
+    // Every case runs a full round of Diffie-Hellman key agreements, so keep the count low.
+    #![proptest_config(ProptestConfig::with_cases(16))]
+
+    #[test]
+    fn pairwise_masks_cancel_for_any_values(values in prop::collection::vec(any::<u32>(), 2..=500), seed in any::<u64>()) {
+        let mut server: Server<Wrapping<u32>> = Server::with_seed(seed, values.len(), 1);
+        for (client, &value) in server.clients.iter_mut().zip(&values) {
+            client.value = vec![Wrapping(value)];
+            client.masked_value = vec![Wrapping(value)];
+        }
+        server.initialize().unwrap();
+        let expected: Wrapping<u32> = values.iter().map(|&value| Wrapping(value)).sum();
+        prop_assert_eq!(server.aggregate(), Ok(vec![expected]));
+    }
+}
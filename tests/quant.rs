@@ -0,0 +1,35 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use secure_aggregation_tutorial::quant;
+use std::num::Wrapping;
+
+#[test]
+fn round_trip_error_is_at_most_half_a_unit() {
+    let mut rng = StdRng::seed_from_u64(0);
+    for scale in [1.0, 100.0, 1000.0] {
+        for _ in 0..1000 {
+            let x: f64 = rng.gen_range(-20.0..20.0);
+            let decoded = quant::dequantize(quant::quantize(x, scale, 32), scale, 1);
+            assert!((decoded - x).abs() <= 0.5 / scale + 1e-12, "{} decoded as {} at scale {}", x, decoded, scale);
+            let decoded = quant::dequantize_i64(quant::quantize_i64(x, scale), scale, 1);
+            assert!((decoded - x).abs() <= 0.5 / scale + 1e-12, "{} decoded as {} at scale {}", x, decoded, scale);
+        }
+    }
+}
+
+#[test]
+fn negative_values_are_stored_in_twos_complement() {
+    assert_eq!(quant::quantize(-0.001, 1000.0, 32), Wrapping(u32::MAX));
+    assert_eq!(quant::quantize(-2.5, 10.0, 32), Wrapping(-25i32 as u32));
+    assert_eq!(quant::dequantize(Wrapping(-25i32 as u32), 10.0, 1), -2.5);
+    assert_eq!(quant::quantize_i64(-2.5, 10.0), Wrapping(-25));
+}
+
+#[test]
+fn values_beyond_the_bits_limit_are_clamped() {
+    assert_eq!(quant::quantize(1.0, 1000.0, 8), Wrapping(127));
+    assert_eq!(quant::quantize(-1.0, 1000.0, 8), Wrapping(-128i32 as u32));
+    assert_eq!(quant::dequantize(quant::quantize(1.0, 1000.0, 8), 1000.0, 1), 0.127);
+    assert_eq!(quant::dequantize(quant::quantize(-1e12, 1.0, 32), 1.0, 1), i32::MIN as f64);
+    // Within the range, a narrow encoding still round-trips.
+    assert_eq!(quant::dequantize(quant::quantize(0.1, 1000.0, 8), 1000.0, 1), 0.1);
+}
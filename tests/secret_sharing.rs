@@ -0,0 +1,44 @@
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use secure_aggregation_tutorial::secret_sharing::{self, Share};
+
+// Every subset of size k of 0..n, in lexicographic order.
+fn subsets(n: usize, k: usize) -> Vec<Vec<usize>> {
+    if k == 0 {
+        return vec![Vec::new()];
+    }
+    (k - 1..n)
+        .flat_map(|last| {
+            subsets(last, k - 1).into_iter().map(move |mut subset| {
+                subset.push(last);
+                subset
+            })
+        })
+        .collect()
+}
+
+fn pick(shares: &[Share], subset: &[usize]) -> Vec<Share> {
+    subset.iter().map(|&i| shares[i]).collect()
+}
+
+#[test]
+fn any_threshold_shares_reconstruct_the_secret() {
+    let mut rng = StdRng::seed_from_u64(0);
+    let secret: [u8; 32] = rng.gen();
+    let shares = secret_sharing::split(secret, 7, 4, &mut rng);
+    assert_eq!(subsets(7, 4).len(), 35);
+    for k in 4..=7 {
+        for subset in subsets(7, k) {
+            assert_eq!(secret_sharing::reconstruct(&pick(&shares, &subset)), secret, "shares {:?}", subset);
+        }
+    }
+}
+
+#[test]
+fn fewer_than_threshold_shares_do_not_reconstruct() {
+    let mut rng = StdRng::seed_from_u64(1);
+    let secret: [u8; 32] = rng.gen();
+    let shares = secret_sharing::split(secret, 7, 4, &mut rng);
+    for subset in subsets(7, 3) {
+        assert_ne!(secret_sharing::reconstruct(&pick(&shares, &subset)), secret, "shares {:?}", subset);
+    }
+}